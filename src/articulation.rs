@@ -0,0 +1,97 @@
+use serde::{Serialize, Deserialize};
+
+// a note shorter than this share of the time until the next one is detached
+const STACCATO_RATIO: f64 = 0.6;
+// and one lasting at least this share is held for its full value
+const HELD_RATIO: f64 = 0.9;
+// drop of the level between two held notes above which they are played separately, in dB
+const LEGATO_DIP_DB: f64 = 3.0;
+// an accented note is this much louder than the typical note of the recording, in dB
+const ACCENT_DB: f64 = 4.0;
+// and reaches its peak at least this fast, in dB per second
+const ACCENT_SLOPE: f64 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Articulation {
+    // held into the next note without a break in the sound
+    Legato,
+    // released well before the next note
+    Staccato,
+    // attacked louder than the notes around it
+    Accent,
+    // held for its full value, yet separated from the next note
+    Tenuto,
+}
+
+// what the energy envelope tells about a single note
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoteEnvelope {
+    // from the attack to the release, in seconds
+    pub duration: f64,
+    // from the attack to the attack of the next note, none for the last note
+    pub inter_onset: Option<f64>,
+    // loudest frame level, in dBFS
+    pub peak: f64,
+    // rise of the level from the attack to the peak, in dB per second
+    pub attack_slope: f64,
+    // fall of the level between the note and the next one, in dB
+    pub dip: f64,
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted.get(sorted.len() / 2).cloned()
+}
+
+// articulation of every note, none when it is played plainly
+pub fn articulate(notes: &[NoteEnvelope]) -> Vec<Option<Articulation>> {
+    let peaks: Vec<f64> = notes.iter().map(|note| note.peak).collect();
+    let typical_peak = median(&peaks).unwrap_or(0.0);
+
+    notes.iter().map(|note| {
+        let ratio = note.inter_onset.map(|ioi| note.duration / ioi);
+        match ratio {
+            Some(ratio) if ratio < STACCATO_RATIO => Some(Articulation::Staccato),
+            _ if note.peak >= typical_peak + ACCENT_DB && note.attack_slope >= ACCENT_SLOPE => Some(Articulation::Accent),
+            Some(ratio) if ratio >= HELD_RATIO && note.dip < LEGATO_DIP_DB => Some(Articulation::Legato),
+            Some(ratio) if ratio >= HELD_RATIO => Some(Articulation::Tenuto),
+            _ => None,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_articulate() {
+        let note = |duration: f64, inter_onset: Option<f64>, peak: f64, attack_slope: f64, dip: f64| NoteEnvelope {
+            duration,
+            inter_onset,
+            peak,
+            attack_slope,
+            dip
+        };
+        let notes = [
+            note(0.1, Some(0.5), -9.0, 500.0, 80.0),
+            note(0.5, Some(0.5), -9.0, 0.0, 0.5),
+            note(0.47, Some(0.5), -9.0, 0.0, 8.0),
+            note(0.3, Some(0.5), -9.0, 0.0, 20.0),
+            note(0.5, Some(0.5), -3.0, 200.0, 0.0),
+            // as loud, but swelling slowly
+            note(0.5, None, -3.0, 20.0, 0.0),
+        ];
+        let expected = [
+            Some(Articulation::Staccato),
+            Some(Articulation::Legato),
+            Some(Articulation::Tenuto),
+            None,
+            Some(Articulation::Accent),
+            None,
+        ];
+        assert_eq!(expected.to_vec(), articulate(&notes));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{fmt::Display, str::FromStr};
+
+// how multichannel audio is turned into the mono signal(s) fed to the pitch detection
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChannelMode {
+    // average every channel into a single signal
+    #[default]
+    Mix,
+    // only keep the given (zero based) channel
+    Pick(u16),
+    // analyse every channel on its own
+    Separate,
+}
+
+impl FromStr for ChannelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mix" => Ok(ChannelMode::Mix),
+            "all" => Ok(ChannelMode::Separate),
+            _ => s.parse::<u16>()
+                .map(ChannelMode::Pick)
+                .map_err(|_| format!("unknown channel mode '{}', expected mix, all or a channel index", s)),
+        }
+    }
+}
+
+impl Display for ChannelMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelMode::Mix => write!(f, "mix"),
+            ChannelMode::Pick(channel) => write!(f, "{}", channel),
+            ChannelMode::Separate => write!(f, "all"),
+        }
+    }
+}
+
+// split interleaved samples into one vector per channel
+pub fn deinterleave(samples: &[f64], channels: u16) -> Vec<Vec<f64>> {
+    let channels = channels as usize;
+    let mut result = vec![Vec::with_capacity(samples.len() / channels); channels];
+    for frame in samples.chunks_exact(channels) {
+        for (channel, sample) in result.iter_mut().zip(frame) {
+            channel.push(*sample);
+        }
+    }
+    result
+}
+
+// average interleaved samples into a single channel
+pub fn downmix(samples: &[f64], channels: u16) -> Vec<f64> {
+    samples
+        .chunks_exact(channels as usize)
+        .map(|frame| frame.iter().sum::<f64>() / channels as f64)
+        .collect()
+}
+
+// apply the channel mode to interleaved samples, None if the picked channel does not exist
+pub fn select_channels(samples: &[f64], channels: u16, mode: ChannelMode) -> Option<Vec<Vec<f64>>> {
+    match mode {
+        ChannelMode::Mix => Some(vec![downmix(samples, channels)]),
+        ChannelMode::Pick(channel) if channel < channels => {
+            Some(vec![deinterleave(samples, channels).swap_remove(channel as usize)])
+        },
+        ChannelMode::Pick(_) => None,
+        ChannelMode::Separate => Some(deinterleave(samples, channels)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deinterleave() {
+        let samples = [1.0, -1.0, 2.0, -2.0, 3.0, -3.0, 4.0];
+        let channels = deinterleave(&samples, 2);
+        assert_eq!(vec![vec![1.0, 2.0, 3.0], vec![-1.0, -2.0, -3.0]], channels);
+    }
+
+    #[test]
+    fn test_downmix() {
+        let samples = [1.0, 0.0, 0.5, 0.5, -1.0, 1.0];
+        assert_eq!(vec![0.5, 0.5, 0.0], downmix(&samples, 2));
+        assert_eq!(samples.to_vec(), downmix(&samples, 1));
+    }
+
+    #[test]
+    fn test_select_channels() {
+        let samples = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert_eq!(Some(vec![vec![2.0, 5.0]]), select_channels(&samples, 3, ChannelMode::Pick(1)));
+        assert_eq!(None, select_channels(&samples, 3, ChannelMode::Pick(3)));
+        assert_eq!(Some(vec![vec![2.0, 5.0]]), select_channels(&samples, 3, ChannelMode::Mix));
+        assert_eq!(3, select_channels(&samples, 3, ChannelMode::Separate).unwrap().len());
+    }
+
+    #[test]
+    fn test_parse_channel_mode() {
+        assert_eq!(Ok(ChannelMode::Mix), "mix".parse());
+        assert_eq!(Ok(ChannelMode::Separate), "all".parse());
+        assert_eq!(Ok(ChannelMode::Pick(2)), "2".parse());
+        assert!("left".parse::<ChannelMode>().is_err());
+    }
+}
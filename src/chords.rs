@@ -0,0 +1,260 @@
+use std::fmt::Display;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{detector::windowed_spectrum, notes::{NOTE_NAMES, SEMITONES_PER_OCTAVE}, window::WindowFunction};
+
+// frequency range folded into the chroma
+const CHROMA_MIN_FREQUENCY: f64 = 60.0;
+const CHROMA_MAX_FREQUENCY: f64 = 5000.0;
+// the bass note is searched for below this frequency
+const BASS_MAX_FREQUENCY: f64 = 300.0;
+// a spectral peak counts as the bass when at least this share of the loudest one
+const BASS_MIN_RELATIVE: f64 = 0.25;
+// partials of every chord tone in the templates, each one weaker by HARMONIC_DECAY
+const TEMPLATE_HARMONICS: usize = 4;
+const HARMONIC_DECAY: f64 = 0.6;
+// cosine similarity below which a frame is not a chord
+const MIN_CHORD_SCORE: f64 = 0.6;
+// similarity a new chord has to gain per frame, during the shortest chord, to be switched to
+const SWITCH_PENALTY: f64 = 0.1;
+// shortest chord, in seconds
+pub const MIN_CHORD_LENGTH: f64 = 0.2;
+pub const NO_CHORD: &str = "N.C.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Dominant7,
+    Major7,
+    Minor7,
+    Diminished,
+    Augmented,
+    Suspended4,
+}
+
+impl ChordQuality {
+    const ALL: [ChordQuality; 8] = [
+        ChordQuality::Major, ChordQuality::Minor, ChordQuality::Dominant7, ChordQuality::Major7,
+        ChordQuality::Minor7, ChordQuality::Diminished, ChordQuality::Augmented, ChordQuality::Suspended4,
+    ];
+
+    // semitones above the root
+    pub fn intervals(&self) -> &'static [usize] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Suspended4 => &[0, 5, 7],
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Suspended4 => "sus4",
+        }
+    }
+}
+
+// pitch classes count semitones from C
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chord {
+    pub root: usize,
+    pub quality: ChordQuality,
+    pub bass: usize,
+}
+
+impl Chord {
+    pub fn pitch_classes(&self) -> Vec<usize> {
+        self.quality.intervals().iter().map(|i| (self.root + i) % SEMITONES_PER_OCTAVE).collect()
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", NOTE_NAMES[self.root], self.quality.suffix())?;
+        if self.bass != self.root {
+            write!(f, "/{}", NOTE_NAMES[self.bass])?;
+        }
+        Ok(())
+    }
+}
+
+// a chord, or none, held over a stretch of a recording
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChordSegment {
+    // the chord symbol, N.C. when no chord is played
+    pub symbol: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+// pitch class of a frequency, A4 being tuned to the reference
+fn pitch_class(frequency: f64, reference: f64) -> usize {
+    // A is 9 semitones above C
+    let semitones = (SEMITONES_PER_OCTAVE as f64 * (frequency / reference).log2()).round() as i64 + 9;
+    semitones.rem_euclid(SEMITONES_PER_OCTAVE as i64) as usize
+}
+
+// energy of every pitch class in a magnitude spectrum, normalized to a unit vector
+pub fn chroma(spectrum: &[f64], bin_width: f64, reference: f64) -> [f64; SEMITONES_PER_OCTAVE] {
+    let mut chroma = [0.0; SEMITONES_PER_OCTAVE];
+    for (k, magnitude) in spectrum.iter().enumerate() {
+        let frequency = k as f64 * bin_width;
+        if (CHROMA_MIN_FREQUENCY..=CHROMA_MAX_FREQUENCY).contains(&frequency) {
+            chroma[pitch_class(frequency, reference)] += magnitude * magnitude;
+        }
+    }
+    let norm = chroma.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        chroma.iter_mut().for_each(|x| *x /= norm);
+    }
+    chroma
+}
+
+// pitch class of the lowest strong peak of a magnitude spectrum
+pub fn bass(spectrum: &[f64], bin_width: f64, reference: f64) -> Option<usize> {
+    let loudest = spectrum.iter().cloned().fold(0.0, f64::max);
+    if loudest == 0.0 {
+        return None;
+    }
+    let first = (CHROMA_MIN_FREQUENCY / bin_width).ceil() as usize;
+    let last = ((BASS_MAX_FREQUENCY / bin_width) as usize).min(spectrum.len() - 2);
+    (first.max(1)..=last)
+        .find(|k| spectrum[*k] >= loudest * BASS_MIN_RELATIVE && spectrum[*k] >= spectrum[k - 1] && spectrum[*k] >= spectrum[k + 1])
+        .map(|k| pitch_class(k as f64 * bin_width, reference))
+}
+
+// chroma a chord is expected to produce, its tones along with their first partials
+fn template(root: usize, quality: ChordQuality) -> [f64; SEMITONES_PER_OCTAVE] {
+    let mut template = [0.0; SEMITONES_PER_OCTAVE];
+    for interval in quality.intervals() {
+        for h in 1..=TEMPLATE_HARMONICS {
+            let semitones = (SEMITONES_PER_OCTAVE as f64 * (h as f64).log2()).round() as usize;
+            template[(root + interval + semitones) % SEMITONES_PER_OCTAVE] += HARMONIC_DECAY.powi(h as i32 - 1);
+        }
+    }
+    let norm = template.iter().map(|x| x * x).sum::<f64>().sqrt();
+    template.iter_mut().for_each(|x| *x /= norm);
+    template
+}
+
+// chord of every frame, smoothed with Viterbi: leaving a chord costs SWITCH_PENALTY for each
+// frame of the shortest chord. frames not sounding, or not close enough to any chord, are no
+// chord. returns the runs of frames holding the same chord, first to last excluded
+pub fn recognize_chords(frames: &[&[f64]], sounding: &[bool], sample_rate: u32, window: WindowFunction, hop_length: f64, reference: f64) -> Vec<(usize, usize, Option<Chord>)> {
+    if frames.is_empty() {
+        return vec![];
+    }
+    let chords: Vec<(usize, ChordQuality)> = (0..SEMITONES_PER_OCTAVE)
+        .flat_map(|root| ChordQuality::ALL.iter().map(move |quality| (root, *quality)))
+        .collect();
+    let templates: Vec<[f64; SEMITONES_PER_OCTAVE]> = chords.iter().map(|(root, quality)| template(*root, *quality)).collect();
+
+    let spectra: Vec<Vec<f64>> = frames.iter()
+        .map(|frame| windowed_spectrum(frame, window).iter().map(|x| x.norm()).collect())
+        .collect();
+    let bin_width = sample_rate as f64 / frames[0].len() as f64;
+    // the state 0 is no chord, scored as a chord just below the threshold
+    let scores: Vec<Vec<f64>> = spectra.iter().zip(sounding).map(|(spectrum, sounding)| {
+        let chroma = chroma(spectrum, bin_width, reference);
+        std::iter::once(MIN_CHORD_SCORE)
+            .chain(templates.iter().map(|template| {
+                if *sounding { template.iter().zip(&chroma).map(|(a, b)| a * b).sum() } else { 0.0 }
+            }))
+            .collect()
+    }).collect();
+
+    let min_frames = (MIN_CHORD_LENGTH / hop_length).ceil().max(1.0);
+    let penalty = SWITCH_PENALTY * min_frames;
+    let states = chords.len() + 1;
+    let mut total = scores[0].clone();
+    let mut origins: Vec<Vec<usize>> = vec![];
+    for score in &scores[1..] {
+        let best = (0..states).max_by(|a, b| total[*a].total_cmp(&total[*b])).unwrap();
+        let mut origin = vec![0; states];
+        total = (0..states).map(|s| {
+            let (from, value) = if total[s] >= total[best] - penalty { (s, total[s]) } else { (best, total[best] - penalty) };
+            origin[s] = from;
+            value + score[s]
+        }).collect();
+        origins.push(origin);
+    }
+    let mut state = (0..states).max_by(|a, b| total[*a].total_cmp(&total[*b])).unwrap();
+    let mut path = vec![state];
+    for origin in origins.iter().rev() {
+        state = origin[state];
+        path.push(state);
+    }
+    path.reverse();
+
+    let mut runs = vec![];
+    let mut first = 0;
+    for k in 1..=path.len() {
+        if k == path.len() || path[k] != path[first] {
+            let chord = (path[first] > 0).then(|| {
+                let (root, quality) = chords[path[first] - 1];
+                // the most frequent bass of the run, when it belongs to the chord
+                let mut counts = [0; SEMITONES_PER_OCTAVE];
+                spectra[first..k].iter().filter_map(|spectrum| bass(spectrum, bin_width, reference)).for_each(|pc| counts[pc] += 1);
+                let bass = (0..SEMITONES_PER_OCTAVE).max_by_key(|pc| counts[*pc]).filter(|pc| counts[*pc] > 0);
+                let chord = Chord { root, quality, bass: root };
+                match bass {
+                    Some(bass) if chord.pitch_classes().contains(&bass) => Chord { bass, ..chord },
+                    _ => chord,
+                }
+            });
+            runs.push((first, k, chord));
+            first = k;
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chord_symbols() {
+        let chord = |root, quality, bass| Chord { root, quality, bass }.to_string();
+        assert_eq!("C", chord(0, ChordQuality::Major, 0));
+        assert_eq!("Am7", chord(9, ChordQuality::Minor7, 9));
+        assert_eq!("G/B", chord(7, ChordQuality::Major, 11));
+        assert_eq!("F#dim", chord(6, ChordQuality::Diminished, 6));
+        assert_eq!("Dsus4", chord(2, ChordQuality::Suspended4, 2));
+    }
+
+    #[test]
+    fn test_pitch_class() {
+        assert_eq!(9, pitch_class(440.0, 440.0));
+        assert_eq!(0, pitch_class(261.63, 440.0));
+        assert_eq!(0, pitch_class(65.41, 440.0));
+        assert_eq!(11, pitch_class(123.47, 440.0));
+        // the A of a baroque ensemble, and its C
+        assert_eq!(9, pitch_class(415.0, 415.0));
+        assert_eq!(0, pitch_class(246.76, 415.0));
+    }
+
+    #[test]
+    fn test_template() {
+        // C major holds its tones, the fifth strongest as the second partial of the root
+        let template = template(0, ChordQuality::Major);
+        let strongest = (0..12).max_by(|a, b| template[*a].total_cmp(&template[*b])).unwrap();
+        assert_eq!(7, strongest);
+        assert!(template[1] == 0.0 && template[4] > 0.0);
+        assert!((template.iter().map(|x| x * x).sum::<f64>() - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,825 @@
+use std::{f64::consts::PI, fmt::Display, str::FromStr};
+
+use rustfft::{FftPlanner, num_complex::Complex};
+use serde::{Serialize, Deserialize};
+
+use crate::window::WindowFunction;
+
+// range of fundamental frequencies the time domain detectors look for (A0 to C8)
+pub const MIN_FREQUENCY: f64 = 27.5;
+pub const MAX_FREQUENCY: f64 = 4186.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PitchEstimate {
+    pub frequency: f64,
+    // between 0.0 and 1.0
+    pub confidence: f64,
+}
+
+pub trait PitchDetector {
+    // estimate the fundamental frequency of a single frame, None when the frame is unpitched
+    fn detect(&self, frame: &[f64], sample_rate: u32) -> Option<PitchEstimate>;
+
+    // estimate every frame of a signal, detectors that smooth their output over time override this
+    fn detect_frames(&self, frames: &[&[f64]], sample_rate: u32) -> Vec<Option<PitchEstimate>> {
+        frames.iter().map(|frame| self.detect(frame, sample_rate)).collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DetectorKind {
+    #[default]
+    Fft,
+    Yin,
+    Pyin,
+    Autocorrelation,
+    Hps,
+    Cepstrum,
+}
+
+impl DetectorKind {
+    pub const ALL: [DetectorKind; 6] = [
+        DetectorKind::Fft,
+        DetectorKind::Yin,
+        DetectorKind::Pyin,
+        DetectorKind::Autocorrelation,
+        DetectorKind::Hps,
+        DetectorKind::Cepstrum,
+    ];
+
+    // interpolation only applies to the FFT peak detector, and the window to the spectral ones
+    pub fn detector(&self, interpolation: PeakInterpolation, window: WindowFunction) -> Box<dyn PitchDetector + Send + Sync> {
+        match self {
+            DetectorKind::Fft => Box::new(FftPeak { interpolation, window }),
+            DetectorKind::Yin => Box::new(Yin::default()),
+            DetectorKind::Pyin => Box::new(Pyin),
+            DetectorKind::Autocorrelation => Box::new(Autocorrelation::default()),
+            DetectorKind::Hps => Box::new(HarmonicProductSpectrum { window, ..Default::default() }),
+            DetectorKind::Cepstrum => Box::new(Cepstrum { window }),
+        }
+    }
+}
+
+impl FromStr for DetectorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fft" => Ok(DetectorKind::Fft),
+            "yin" => Ok(DetectorKind::Yin),
+            "pyin" => Ok(DetectorKind::Pyin),
+            "autocorrelation" => Ok(DetectorKind::Autocorrelation),
+            "hps" => Ok(DetectorKind::Hps),
+            "cepstrum" => Ok(DetectorKind::Cepstrum),
+            _ => Err(format!("unknown pitch detector '{}'", s)),
+        }
+    }
+}
+
+impl Display for DetectorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DetectorKind::Fft => write!(f, "fft"),
+            DetectorKind::Yin => write!(f, "yin"),
+            DetectorKind::Pyin => write!(f, "pyin"),
+            DetectorKind::Autocorrelation => write!(f, "autocorrelation"),
+            DetectorKind::Hps => write!(f, "hps"),
+            DetectorKind::Cepstrum => write!(f, "cepstrum"),
+        }
+    }
+}
+
+// how the FFT peak is refined between bins
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PeakInterpolation {
+    None,
+    // parabola through the magnitudes of the peak and its neighbours
+    Quadratic,
+    // parabola through the log magnitudes, exact for a Gaussian shaped peak
+    #[default]
+    Gaussian,
+    // phase advance of the peak between two overlapping sub frames
+    PhaseVocoder,
+}
+
+impl FromStr for PeakInterpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(PeakInterpolation::None),
+            "quadratic" => Ok(PeakInterpolation::Quadratic),
+            "gaussian" => Ok(PeakInterpolation::Gaussian),
+            "phase" => Ok(PeakInterpolation::PhaseVocoder),
+            _ => Err(format!("unknown peak interpolation '{}'", s)),
+        }
+    }
+}
+
+impl Display for PeakInterpolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeakInterpolation::None => write!(f, "none"),
+            PeakInterpolation::Quadratic => write!(f, "quadratic"),
+            PeakInterpolation::Gaussian => write!(f, "gaussian"),
+            PeakInterpolation::PhaseVocoder => write!(f, "phase"),
+        }
+    }
+}
+
+// the phase vocoder compares the frame without its last and without its first 1/PHASE_HOP_DIVISOR
+const PHASE_HOP_DIVISOR: usize = 8;
+
+// pick the strongest bin of the magnitude spectrum
+#[derive(Default)]
+pub struct FftPeak {
+    pub interpolation: PeakInterpolation,
+    pub window: WindowFunction,
+}
+
+impl FftPeak {
+    pub fn new(interpolation: PeakInterpolation) -> FftPeak {
+        FftPeak {
+            interpolation,
+            window: WindowFunction::default()
+        }
+    }
+
+    fn strongest_bin(spectrum: &[Complex<f64>]) -> Option<usize> {
+        spectrum.iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.norm().total_cmp(&b.norm()))
+            .map(|(k, _)| k)
+    }
+
+    // frequency of the strongest peak measured from its phase advance over a hop
+    fn phase_vocoder(&self, frame: &[f64], sample_rate: u32) -> Option<f64> {
+        let hop = frame.len() / PHASE_HOP_DIVISOR;
+        let size = frame.len() - hop;
+        if hop == 0 {
+            return None;
+        }
+        let first = windowed_spectrum(&frame[..size], self.window);
+        let second = windowed_spectrum(&frame[hop..], self.window);
+        let k = FftPeak::strongest_bin(&first)?;
+
+        let expected = 2.0 * PI * k as f64 * hop as f64 / size as f64;
+        let deviation = second[k].arg() - first[k].arg() - expected;
+        // wrap into [-pi, pi]
+        let deviation = deviation - 2.0 * PI * (deviation / (2.0 * PI)).round();
+        let offset = deviation * size as f64 / (2.0 * PI * hop as f64);
+        Some((k as f64 + offset) * sample_rate as f64 / size as f64)
+    }
+}
+
+impl PitchDetector for FftPeak {
+    fn detect(&self, frame: &[f64], sample_rate: u32) -> Option<PitchEstimate> {
+        let n = frame.len();
+        if n < 4 {
+            return None;
+        }
+        let bin = sample_rate as f64 / n as f64;
+        let spectrum = windowed_spectrum(frame, self.window);
+        let magnitudes: Vec<f64> = spectrum.iter().map(|x| x.norm()).collect();
+        let total = magnitudes.iter().sum::<f64>();
+        if total == 0.0 {
+            return None;
+        }
+        let k = FftPeak::strongest_bin(&spectrum)?;
+
+        let frequency = match self.interpolation {
+            PeakInterpolation::None => k as f64 * bin,
+            PeakInterpolation::Quadratic => parabolic_lag(&magnitudes, k) * bin,
+            PeakInterpolation::Gaussian => {
+                let floor = magnitudes[k] * 1e-12;
+                let logs: Vec<f64> = magnitudes[k.saturating_sub(1)..(k + 2).min(magnitudes.len())].iter()
+                    .map(|m| (m + floor).ln())
+                    .collect();
+                (k.saturating_sub(1) as f64 + parabolic_lag(&logs, k.min(1))) * bin
+            },
+            PeakInterpolation::PhaseVocoder => self.phase_vocoder(frame, sample_rate)?,
+        };
+
+        Some(PitchEstimate {
+            frequency,
+            confidence: magnitudes[k] / total
+        })
+    }
+}
+
+// lags searched for a period, bounded by the frequency range and half the frame
+fn lag_range(frame_len: usize, sample_rate: u32) -> Option<(usize, usize)> {
+    let tau_min = (sample_rate as f64 / MAX_FREQUENCY).floor().max(2.0) as usize;
+    let tau_max = ((sample_rate as f64 / MIN_FREQUENCY).ceil() as usize).min(frame_len / 2);
+    if tau_max <= tau_min + 1 {
+        return None;
+    }
+    Some((tau_min, tau_max))
+}
+
+// correlation of the first half of the frame with the frame shifted by every lag in 0..=tau_max,
+// along with the energy of the shifted window. the correlation is computed with an FFT.
+fn lagged_correlation(frame: &[f64], tau_max: usize) -> (Vec<f64>, Vec<f64>) {
+    let window = frame.len() / 2;
+    let size = window + tau_max;
+
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(size);
+    let ifft = planner.plan_fft_inverse(size);
+    let mut head: Vec<Complex<f64>> = (0..size)
+        .map(|i| Complex::new(if i < window { frame[i] } else { 0.0 }, 0.0))
+        .collect();
+    let mut full: Vec<Complex<f64>> = frame[..size].iter().map(|&x| Complex::new(x, 0.0)).collect();
+    fft.process(&mut head);
+    fft.process(&mut full);
+    let mut correlation: Vec<Complex<f64>> = head.iter().zip(&full).map(|(a, b)| a.conj() * b).collect();
+    ifft.process(&mut correlation);
+
+    // energy of every window position, from a running sum of squares
+    let mut squares = vec![0.0; size + 1];
+    for i in 0..size {
+        squares[i + 1] = squares[i] + frame[i] * frame[i];
+    }
+
+    (
+        correlation.iter().take(tau_max + 1).map(|x| x.re / size as f64).collect(),
+        (0..=tau_max).map(|tau| squares[tau + window] - squares[tau]).collect()
+    )
+}
+
+// cumulative mean normalized difference function of YIN, computed for lags 0..=tau_max over a
+// window of half the frame
+pub fn cmndf(frame: &[f64], tau_max: usize) -> Vec<f64> {
+    let (correlation, energy) = lagged_correlation(frame, tau_max);
+
+    let mut result = vec![1.0; tau_max + 1];
+    let mut running_sum = 0.0;
+    for (tau, value) in result.iter_mut().enumerate().skip(1) {
+        let difference = (energy[0] + energy[tau] - 2.0 * correlation[tau]).max(0.0);
+        running_sum += difference;
+        *value = if running_sum > 0.0 { difference * tau as f64 / running_sum } else { 1.0 };
+    }
+    result
+}
+
+// normalized square difference function (McLeod), an autocorrelation normalized to [-1, 1]
+pub fn nsdf(frame: &[f64], tau_max: usize) -> Vec<f64> {
+    let (correlation, energy) = lagged_correlation(frame, tau_max);
+    correlation.iter()
+        .zip(&energy)
+        .map(|(c, e)| {
+            let total = energy[0] + e;
+            if total > 0.0 { 2.0 * c / total } else { 0.0 }
+        })
+        .collect()
+}
+
+// spectrum of a windowed frame, up to the Nyquist frequency
+pub fn windowed_spectrum(frame: &[f64], window: WindowFunction) -> Vec<Complex<f64>> {
+    let n = frame.len();
+    let mut data: Vec<Complex<f64>> = window.apply(frame)
+        .iter()
+        .map(|x| Complex::new(*x, 0.0))
+        .collect();
+    FftPlanner::new().plan_fft_forward(n).process(&mut data);
+    data.truncate(n / 2);
+    data
+}
+
+fn magnitude_spectrum(frame: &[f64], window: WindowFunction) -> Vec<f64> {
+    windowed_spectrum(frame, window).iter().map(|x| x.norm()).collect()
+}
+
+// refine the position of an extremum with a parabola through its neighbours
+pub fn parabolic_lag(values: &[f64], tau: usize) -> f64 {
+    if tau == 0 || tau + 1 >= values.len() {
+        return tau as f64;
+    }
+    let (a, b, c) = (values[tau - 1], values[tau], values[tau + 1]);
+    let denominator = a - 2.0 * b + c;
+    if denominator == 0.0 {
+        return tau as f64;
+    }
+    tau as f64 + 0.5 * (a - c) / denominator
+}
+
+// first lag whose value dips below the threshold, followed down to its local minimum
+fn first_dip(values: &[f64], tau_min: usize, threshold: f64) -> Option<usize> {
+    let mut tau = tau_min;
+    while tau < values.len() {
+        if values[tau] < threshold {
+            while tau + 1 < values.len() && values[tau + 1] < values[tau] {
+                tau += 1;
+            }
+            return Some(tau);
+        }
+        tau += 1;
+    }
+    None
+}
+
+fn global_minimum(values: &[f64], tau_min: usize) -> usize {
+    (tau_min..values.len())
+        .min_by(|a, b| values[*a].total_cmp(&values[*b]))
+        .unwrap_or(tau_min)
+}
+
+// de Cheveigné & Kawahara YIN estimator
+pub struct Yin {
+    pub threshold: f64,
+}
+
+impl Default for Yin {
+    fn default() -> Self {
+        Yin {
+            threshold: 0.15
+        }
+    }
+}
+
+impl PitchDetector for Yin {
+    fn detect(&self, frame: &[f64], sample_rate: u32) -> Option<PitchEstimate> {
+        let (tau_min, tau_max) = lag_range(frame.len(), sample_rate)?;
+        let values = cmndf(frame, tau_max);
+
+        // without any dip below the threshold the frame is considered unpitched
+        let tau = first_dip(&values, tau_min, self.threshold)?;
+        let period = parabolic_lag(&values, tau);
+        Some(PitchEstimate {
+            frequency: sample_rate as f64 / period,
+            confidence: (1.0 - values[tau]).clamp(0.0, 1.0)
+        })
+    }
+}
+
+// the first lag whose normalized autocorrelation gets close to the best one, which avoids picking
+// a multiple of the period
+pub struct Autocorrelation {
+    pub peak_ratio: f64,
+    pub min_clarity: f64,
+}
+
+impl Default for Autocorrelation {
+    fn default() -> Self {
+        Autocorrelation {
+            peak_ratio: 0.9,
+            min_clarity: 0.5
+        }
+    }
+}
+
+impl PitchDetector for Autocorrelation {
+    fn detect(&self, frame: &[f64], sample_rate: u32) -> Option<PitchEstimate> {
+        let (tau_min, tau_max) = lag_range(frame.len(), sample_rate)?;
+        let values = nsdf(frame, tau_max);
+
+        // local maxima of the function within the lag range
+        let peaks: Vec<usize> = (tau_min.max(1)..tau_max)
+            .filter(|tau| values[*tau] > 0.0 && values[*tau] >= values[tau - 1] && values[*tau] > values[tau + 1])
+            .collect();
+        let best = peaks.iter().map(|tau| values[*tau]).fold(0.0, f64::max);
+        if best < self.min_clarity {
+            return None;
+        }
+        let tau = *peaks.iter().find(|tau| values[**tau] >= best * self.peak_ratio)?;
+
+        let period = parabolic_lag(&values, tau);
+        Some(PitchEstimate {
+            frequency: sample_rate as f64 / period,
+            confidence: values[tau].clamp(0.0, 1.0)
+        })
+    }
+}
+
+// harmonic product spectrum: the spectrum is multiplied with its downsampled copies so the
+// harmonics of the fundamental pile up on its bin. needs harmonic rich signals.
+pub struct HarmonicProductSpectrum {
+    pub harmonics: usize,
+    pub window: WindowFunction,
+}
+
+impl Default for HarmonicProductSpectrum {
+    fn default() -> Self {
+        HarmonicProductSpectrum {
+            harmonics: 5,
+            window: WindowFunction::default()
+        }
+    }
+}
+
+impl PitchDetector for HarmonicProductSpectrum {
+    fn detect(&self, frame: &[f64], sample_rate: u32) -> Option<PitchEstimate> {
+        let n = frame.len();
+        let bin = sample_rate as f64 / n as f64;
+        let spectrum = magnitude_spectrum(frame, self.window);
+        let first = ((MIN_FREQUENCY / bin).floor() as usize).max(1);
+        let last = ((MAX_FREQUENCY / bin).ceil() as usize).min(spectrum.len() / self.harmonics);
+        if last <= first {
+            return None;
+        }
+
+        // sum of logarithms rather than a product to stay away from underflows
+        let floor = spectrum.iter().cloned().fold(0.0, f64::max) * 1e-9;
+        if floor == 0.0 {
+            return None;
+        }
+        // the r-th harmonic of a fundamental falling anywhere in bin k lies within r/2 bins of k*r
+        let harmonic = |k: usize, r: usize| {
+            spectrum[k * r - r / 2..(k * r + r / 2 + 1).min(spectrum.len())].iter().cloned().fold(0.0, f64::max)
+        };
+        let products: Vec<f64> = (first..last)
+            .map(|k| (1..=self.harmonics).map(|r| (harmonic(k, r) + floor).ln()).sum::<f64>())
+            .collect();
+        let (index, _) = products.iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))?;
+
+        // share of the spectral energy carried by the harmonics of the winner
+        let total = spectrum.iter().map(|x| x * x).sum::<f64>();
+        let harmonic = (1..=self.harmonics).map(|r| {
+            let k = (index + first) * r;
+            spectrum[k - 1..=(k + 1).min(spectrum.len() - 1)].iter().map(|x| x * x).sum::<f64>()
+        }).sum::<f64>();
+        Some(PitchEstimate {
+            frequency: (index + first) as f64 * bin,
+            confidence: (harmonic / total).clamp(0.0, 1.0)
+        })
+    }
+}
+
+// real cepstrum: the harmonics of a periodic signal form a peak at the quefrency of its period.
+// needs harmonic rich signals.
+#[derive(Default)]
+pub struct Cepstrum {
+    pub window: WindowFunction,
+}
+
+impl PitchDetector for Cepstrum {
+    fn detect(&self, frame: &[f64], sample_rate: u32) -> Option<PitchEstimate> {
+        let (tau_min, tau_max) = lag_range(frame.len(), sample_rate)?;
+        let spectrum = magnitude_spectrum(frame, self.window);
+        let floor = spectrum.iter().cloned().fold(0.0, f64::max) * 1e-3;
+        if floor == 0.0 {
+            return None;
+        }
+
+        // the log spectrum is mirrored back to a full, real, spectrum before its inverse transform
+        let n = frame.len();
+        let mut data: Vec<Complex<f64>> = (0..n)
+            .map(|k| {
+                let k = if k < n / 2 { k } else { (n - k).min(n / 2 - 1) };
+                Complex::new((spectrum[k] + floor).ln(), 0.0)
+            })
+            .collect();
+        FftPlanner::new().plan_fft_inverse(n).process(&mut data);
+        let cepstrum: Vec<f64> = data.iter().take(tau_max + 2).map(|x| x.re / n as f64).collect();
+
+        let quefrency = (tau_min..=tau_max)
+            .max_by(|a, b| cepstrum[*a].total_cmp(&cepstrum[*b]))?;
+        let peak = cepstrum[quefrency];
+        if peak <= 0.0 {
+            return None;
+        }
+        let rms = ((tau_min..=tau_max).map(|q| cepstrum[q] * cepstrum[q]).sum::<f64>() / (tau_max - tau_min + 1) as f64).sqrt();
+
+        let period = parabolic_lag(&cepstrum, quefrency);
+        Some(PitchEstimate {
+            frequency: sample_rate as f64 / period,
+            confidence: (1.0 - rms / peak).clamp(0.0, 1.0)
+        })
+    }
+}
+
+// pYIN parameters, see Mauch & Dixon, "pYIN: a fundamental frequency estimator using probabilistic
+// threshold distributions"
+const PYIN_THRESHOLDS: usize = 100;
+// weight given to the global minimum when no dip is found below a threshold
+const PYIN_ABSOLUTE_MIN_WEIGHT: f64 = 0.01;
+const PYIN_BIN_CENTS: f64 = 20.0;
+// largest pitch move between two frames, in bins
+const PYIN_MAX_JUMP: usize = 25;
+// probability to jump anywhere, so large intervals between frames remain reachable
+const PYIN_JUMP_ANYWHERE: f64 = 1e-4;
+const PYIN_VOICING_SWITCH: f64 = 0.01;
+// how much the candidates are trusted to be voiced at all
+const PYIN_YIN_TRUST: f64 = 0.5;
+
+// probabilistic YIN: every frame yields several period candidates, a hidden Markov model over
+// pitch bins and voicing then picks the smoothest path with Viterbi decoding
+pub struct Pyin;
+
+impl Pyin {
+    // Beta(2, 18) distributed thresholds and their probability
+    fn thresholds() -> Vec<(f64, f64)> {
+        let thresholds: Vec<(f64, f64)> = (1..=PYIN_THRESHOLDS)
+            .map(|i| i as f64 / PYIN_THRESHOLDS as f64)
+            .map(|t| (t, t * (1.0 - t).powi(17)))
+            .collect();
+        let total = thresholds.iter().map(|(_, p)| p).sum::<f64>();
+        thresholds.into_iter().map(|(t, p)| (t, p / total)).collect()
+    }
+
+    // candidate (frequency, probability) pairs of a frame
+    pub fn candidates(frame: &[f64], sample_rate: u32) -> Vec<(f64, f64)> {
+        let (tau_min, tau_max) = match lag_range(frame.len(), sample_rate) {
+            Some(range) => range,
+            None => return vec![]
+        };
+        // digital silence has no period at all
+        if frame.iter().all(|x| *x == 0.0) {
+            return vec![];
+        }
+        let values = cmndf(frame, tau_max);
+
+        let mut probabilities = vec![0.0; values.len()];
+        for (threshold, probability) in Pyin::thresholds() {
+            match first_dip(&values, tau_min, threshold) {
+                Some(tau) => probabilities[tau] += probability,
+                None => probabilities[global_minimum(&values, tau_min)] += probability * PYIN_ABSOLUTE_MIN_WEIGHT
+            }
+        }
+
+        probabilities.iter()
+            .enumerate()
+            .filter(|(_, p)| **p > 0.0)
+            .map(|(tau, p)| (sample_rate as f64 / parabolic_lag(&values, tau), *p))
+            .collect()
+    }
+
+    fn bins() -> usize {
+        (1200.0 * (MAX_FREQUENCY / MIN_FREQUENCY).log2() / PYIN_BIN_CENTS).ceil() as usize + 1
+    }
+
+    fn bin_of(frequency: f64) -> Option<usize> {
+        if !(MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
+            return None;
+        }
+        Some((1200.0 * (frequency / MIN_FREQUENCY).log2() / PYIN_BIN_CENTS).round() as usize)
+    }
+}
+
+impl PitchDetector for Pyin {
+    fn detect(&self, frame: &[f64], sample_rate: u32) -> Option<PitchEstimate> {
+        self.detect_frames(&[frame], sample_rate).remove(0)
+    }
+
+    fn detect_frames(&self, frames: &[&[f64]], sample_rate: u32) -> Vec<Option<PitchEstimate>> {
+        let bins = Pyin::bins();
+        // states 0..bins are voiced, bins..2*bins are the unvoiced twins of the same pitches
+        let states = 2 * bins;
+        let tiny = f64::MIN_POSITIVE;
+
+        // triangular pitch transition weights
+        let jump_weights: Vec<f64> = (0..=PYIN_MAX_JUMP).map(|d| (PYIN_MAX_JUMP + 1 - d) as f64).collect();
+        let jump_total = jump_weights[0] + 2.0 * jump_weights[1..].iter().sum::<f64>();
+        let jump_log: Vec<f64> = jump_weights.iter()
+            .map(|w| ((1.0 - PYIN_JUMP_ANYWHERE) * w / jump_total).ln())
+            .collect();
+        let anywhere_log = (PYIN_JUMP_ANYWHERE / bins as f64).ln();
+        let stay_log = (1.0 - PYIN_VOICING_SWITCH).ln();
+        let switch_log = PYIN_VOICING_SWITCH.ln();
+
+        let mut candidates = Vec::with_capacity(frames.len());
+        let mut observations = Vec::with_capacity(frames.len());
+        for frame in frames {
+            let frame_candidates = Pyin::candidates(frame, sample_rate);
+            let mut observation = vec![0.0; states];
+            let mut voiced = 0.0;
+            for (frequency, probability) in &frame_candidates {
+                if let Some(bin) = Pyin::bin_of(*frequency) {
+                    observation[bin] += probability * PYIN_YIN_TRUST;
+                    voiced += probability * PYIN_YIN_TRUST;
+                }
+            }
+            let unvoiced = (1.0 - voiced).max(0.0) / bins as f64;
+            for value in observation[bins..].iter_mut() {
+                *value = unvoiced;
+            }
+            observations.push(observation.iter().map(|p| (p + tiny).ln()).collect::<Vec<_>>());
+            candidates.push(frame_candidates);
+        }
+
+        // Viterbi decoding
+        let mut backpointers: Vec<Vec<usize>> = Vec::with_capacity(frames.len());
+        let mut delta: Vec<f64> = observations.first().cloned().unwrap_or_default();
+        for observation in observations.iter().skip(1) {
+            let (best_state, best_score) = delta.iter()
+                .enumerate()
+                .fold((0, f64::NEG_INFINITY), |best, (i, v)| if *v > best.1 { (i, *v) } else { best });
+
+            let mut next = vec![f64::NEG_INFINITY; states];
+            let mut pointers = vec![0; states];
+            for state in 0..states {
+                let bin = state % bins;
+                let voiced = state < bins;
+                // jumping anywhere, whatever the voicing
+                let mut best = (best_state, best_score + anywhere_log + switch_log.max(stay_log));
+                for previous_bin in bin.saturating_sub(PYIN_MAX_JUMP)..=(bin + PYIN_MAX_JUMP).min(bins - 1) {
+                    let jump = jump_log[previous_bin.abs_diff(bin)];
+                    for previous_voiced in [true, false] {
+                        let previous = if previous_voiced { previous_bin } else { previous_bin + bins };
+                        let voicing = if previous_voiced == voiced { stay_log } else { switch_log };
+                        let score = delta[previous] + jump + voicing;
+                        if score > best.1 {
+                            best = (previous, score);
+                        }
+                    }
+                }
+                next[state] = best.1 + observation[state];
+                pointers[state] = best.0;
+            }
+            delta = next;
+            backpointers.push(pointers);
+        }
+
+        let mut path = vec![0; frames.len()];
+        if let Some(last) = path.last_mut() {
+            *last = delta.iter()
+                .enumerate()
+                .fold((0, f64::NEG_INFINITY), |best, (i, v)| if *v > best.1 { (i, *v) } else { best })
+                .0;
+        }
+        for i in (1..frames.len()).rev() {
+            path[i - 1] = backpointers[i - 1][path[i]];
+        }
+
+        path.iter().zip(&candidates).map(|(state, frame_candidates)| {
+            if *state >= bins {
+                return None;
+            }
+            // report the most likely candidate of the decoded bin rather than the bin center
+            frame_candidates.iter()
+                .filter(|(frequency, _)| Pyin::bin_of(*frequency) == Some(*state))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(frequency, _)| PitchEstimate {
+                    frequency: *frequency,
+                    confidence: frame_candidates.iter()
+                        .filter(|(f, _)| Pyin::bin_of(*f) == Some(*state))
+                        .map(|(_, p)| p)
+                        .sum::<f64>()
+                        .min(1.0)
+                })
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn harmonics(frequency: f64, amplitudes: &[f64], len: usize) -> Vec<f64> {
+        (0..len).map(|i| {
+            let t = i as f64 / 44100.0;
+            amplitudes.iter()
+                .enumerate()
+                .map(|(h, a)| a * (2.0 * PI * frequency * (h + 1) as f64 * t).sin())
+                .sum()
+        }).collect()
+    }
+
+    #[test]
+    fn test_cmndf() {
+        let frame = harmonics(441.0, &[1.0], 2048);
+        let values = cmndf(&frame, 300);
+        assert_eq!(1.0, values[0]);
+        // 441Hz repeats exactly every 100 samples
+        assert!(values[100] < 1e-6);
+        assert!(values[200] < 1e-6);
+        assert!(values[50] > 1.0);
+    }
+
+    #[test]
+    fn test_yin_sine() {
+        for frequency in [55.0, 110.0, 261.626, 440.0, 1046.502] {
+            let frame = harmonics(frequency, &[0.8], 4096);
+            let estimate = Yin::default().detect(&frame, 44100).unwrap();
+            assert!((estimate.frequency - frequency).abs() < frequency * 0.001, "{} -> {}", frequency, estimate.frequency);
+            assert!(estimate.confidence > 0.9);
+        }
+    }
+
+    #[test]
+    fn test_yin_weak_fundamental() {
+        // the second harmonic dominates the spectrum
+        let frame = harmonics(220.0, &[0.2, 0.8, 0.3], 4096);
+        let fft = FftPeak::default().detect(&frame, 44100).unwrap();
+        let yin = Yin::default().detect(&frame, 44100).unwrap();
+        assert!((fft.frequency - 440.0).abs() < 11.0);
+        assert!((yin.frequency - 220.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_nsdf() {
+        let frame = harmonics(441.0, &[1.0], 2048);
+        let values = nsdf(&frame, 300);
+        assert!((values[0] - 1.0).abs() < 1e-9);
+        assert!((values[100] - 1.0).abs() < 1e-6);
+        assert!((values[50] + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_harmonic_detectors() {
+        let detectors: [(&str, Box<dyn PitchDetector>, f64); 5] = [
+            ("yin", Box::new(Yin::default()), 0.5),
+            ("pyin", Box::new(Pyin), 0.5),
+            ("autocorrelation", Box::new(Autocorrelation::default()), 0.5),
+            ("hps", Box::new(HarmonicProductSpectrum::default()), 44100.0 / 16384.0),
+            ("cepstrum", Box::new(Cepstrum::default()), 2.0),
+        ];
+        // a sawtooth like spectrum
+        let amplitudes: Vec<f64> = (1..=20).map(|h| 0.5 / h as f64).collect();
+        for frequency in [98.0, 196.0, 440.0] {
+            let frame = harmonics(frequency, &amplitudes, 16384);
+            for (name, detector, tolerance) in &detectors {
+                let estimate = detector.detect(&frame, 44100).unwrap();
+                assert!((estimate.frequency - frequency).abs() <= *tolerance, "{} {} -> {}", name, frequency, estimate.frequency);
+                assert!(estimate.confidence > 0.5, "{} {} confidence {}", name, frequency, estimate.confidence);
+            }
+        }
+    }
+
+    #[test]
+    fn test_detectors_silence() {
+        let frame = vec![0.0; 4096];
+        for kind in DetectorKind::ALL {
+            assert_eq!(None, kind.detector(PeakInterpolation::default(), WindowFunction::default()).detect(&frame, 44100), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_yin_noise() {
+        // a cheap deterministic noise
+        let mut seed: u32 = 1;
+        let frame: Vec<f64> = (0..4096).map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as f64 / 32768.0 - 1.0
+        }).collect();
+        assert_eq!(None, Yin::default().detect(&frame, 44100));
+    }
+
+    #[test]
+    fn test_pyin_frames() {
+        let a4 = harmonics(440.0, &[0.8], 4096);
+        let e4 = harmonics(329.628, &[0.3, 0.8], 4096);
+        let silence = vec![0.0; 4096];
+        let frames: Vec<&[f64]> = vec![&a4, &a4, &e4, &e4, &silence, &silence, &a4];
+
+        let estimates = Pyin.detect_frames(&frames, 44100);
+        let frequencies: Vec<Option<f64>> = estimates.iter().map(|e| e.map(|x| x.frequency.round())).collect();
+        assert_eq!(vec![Some(440.0), Some(440.0), Some(330.0), Some(330.0), None, None, Some(440.0)], frequencies);
+    }
+
+    fn cents(frequency: f64, reference: f64) -> f64 {
+        1200.0 * (frequency / reference).log2()
+    }
+
+    #[test]
+    fn test_peak_interpolation() {
+        // 23ms and 46ms frames, where a bin is 43Hz and 21.5Hz wide
+        let dataset = [
+            (PeakInterpolation::None, 60.0),
+            (PeakInterpolation::Quadratic, 25.0),
+            (PeakInterpolation::Gaussian, 3.0),
+            (PeakInterpolation::PhaseVocoder, 1.0),
+        ];
+        for size in [1024, 2048] {
+            for frequency in [443.3, 523.251, 987.767, 1234.5] {
+                let frame = harmonics(frequency, &[0.8], size);
+                for (interpolation, max_cents) in dataset {
+                    let estimate = FftPeak::new(interpolation).detect(&frame, 44100).unwrap();
+                    let error = cents(estimate.frequency, frequency).abs();
+                    assert!(error < max_cents, "{} {} {}: {} cents", interpolation, size, frequency, error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_peak_windows() {
+        let frame = harmonics(443.3, &[0.8], 2048);
+        for window in [WindowFunction::Hann, WindowFunction::Hamming, WindowFunction::BlackmanHarris, WindowFunction::Kaiser(8.6)] {
+            let detector = FftPeak {
+                interpolation: PeakInterpolation::Gaussian,
+                window
+            };
+            let estimate = detector.detect(&frame, 44100).unwrap();
+            assert!(cents(estimate.frequency, 443.3).abs() < 5.0, "{}: {}", window, estimate.frequency);
+        }
+    }
+
+    #[test]
+    fn test_parse_peak_interpolation() {
+        for interpolation in [PeakInterpolation::None, PeakInterpolation::Quadratic, PeakInterpolation::Gaussian, PeakInterpolation::PhaseVocoder] {
+            assert_eq!(Ok(interpolation), interpolation.to_string().parse());
+        }
+        assert!("cubic".parse::<PeakInterpolation>().is_err());
+    }
+
+    #[test]
+    fn test_parse_detector_kind() {
+        for kind in DetectorKind::ALL {
+            assert_eq!(Ok(kind), kind.to_string().parse());
+        }
+        assert!("crepe".parse::<DetectorKind>().is_err());
+    }
+}
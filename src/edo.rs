@@ -0,0 +1,243 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{notes::{NOTES_PER_OCTAVE, Pitch}, seqdatastruct::SeqData};
+
+// fewest and most steps an octave is divided in
+pub const MIN_DIVISIONS: usize = 5;
+pub const MAX_DIVISIONS: usize = 72;
+const LETTERS: [&str; NOTES_PER_OCTAVE as usize] = ["C", "D", "E", "F", "G", "A", "B"];
+// fifths above C of the natural notes
+const LETTER_FIFTHS: [i64; NOTES_PER_OCTAVE as usize] = [0, 2, 4, -1, 1, 3, 5];
+// semitones above C of the natural notes, giving the nearest midi note
+const LETTER_SEMITONES: [i64; NOTES_PER_OCTAVE as usize] = [0, 2, 4, 5, 7, 9, 11];
+const A_LETTER: usize = 5;
+// most sharps or flats on a note, in half sharps
+const MAX_HALF_SHARPS: i64 = 4;
+const OCTAVES: i64 = 10;
+
+// how the steps between the natural notes are named
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Accidentals {
+    #[default]
+    Sharps,
+    Flats,
+    // half sharps and half flats, written + and d, when a sharp is an even number of steps
+    QuarterTones,
+}
+
+impl FromStr for Accidentals {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sharps" => Ok(Accidentals::Sharps),
+            "flats" => Ok(Accidentals::Flats),
+            "quarter-tones" => Ok(Accidentals::QuarterTones),
+            _ => Err(format!("unknown accidentals '{}'", s)),
+        }
+    }
+}
+
+impl Display for Accidentals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Accidentals::Sharps => write!(f, "sharps"),
+            Accidentals::Flats => write!(f, "flats"),
+            Accidentals::QuarterTones => write!(f, "quarter-tones"),
+        }
+    }
+}
+
+fn accidental_name(half_sharps: i64) -> &'static str {
+    match half_sharps {
+        -4 => "bb",
+        -3 => "db",
+        -2 => "b",
+        -1 => "d",
+        1 => "+",
+        2 => "#",
+        3 => "#+",
+        4 => "##",
+        _ => "",
+    }
+}
+
+// a note of the division named from the chain of fifths, as a natural note with its
+// accidental and the steps it is raised by, written ^, or lowered by, written v
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Spelling {
+    letter: usize,
+    half_sharps: i64,
+    ups: i64,
+    // octave of the name, the B# of an octave being the C of the next one
+    octave: i64,
+}
+
+// equal division of the octave in any number of steps, with A4 at the reference frequency
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EqualDivision {
+    pub divisions: usize,
+    pub accidentals: Accidentals,
+}
+
+impl EqualDivision {
+    // the step nearest to a pure fifth
+    fn fifth(&self) -> i64 {
+        (self.divisions as f64 * 1.5f64.log2()).round() as i64
+    }
+
+    // steps a sharp raises a note by, seven fifths less four octaves
+    fn sharp(&self) -> i64 {
+        7 * self.fifth() - 4 * self.divisions as i64
+    }
+
+    // steps of a natural note above the C of its octave
+    fn letter_step(&self, letter: usize) -> i64 {
+        (LETTER_FIFTHS[letter] * self.fifth()).rem_euclid(self.divisions as i64)
+    }
+
+    // the name of a step with the fewest accidentals, a sharp or a flat counting as much as an
+    // up or a down and a half sharp or half flat as half of it. double sharps and flats come
+    // last, and ties go to the fewest ups and downs
+    fn spell(&self, octave: i64, step: i64) -> Spelling {
+        let divisions = self.divisions as i64;
+        let sharp = self.sharp();
+        let halves = self.accidentals == Accidentals::QuarterTones && sharp % 2 == 0;
+        (0..LETTERS.len())
+            .flat_map(|letter| (-MAX_HALF_SHARPS..=MAX_HALF_SHARPS).map(move |half_sharps| (letter, half_sharps)))
+            .filter(|(_, half_sharps)| halves || half_sharps % 2 == 0)
+            .map(|(letter, half_sharps)| {
+                let nominal = self.letter_step(letter) + half_sharps * sharp / 2;
+                let mut ups = (step - nominal).rem_euclid(divisions);
+                if ups > divisions / 2 {
+                    ups -= divisions;
+                }
+                let flat_side = if self.accidentals == Accidentals::Flats { half_sharps > 0 } else { half_sharps < 0 };
+                let cost = (half_sharps.abs() > 3, 2 * ups.abs() + half_sharps.abs(), ups.abs(), flat_side, ups < 0);
+                (cost, Spelling {
+                    letter,
+                    half_sharps,
+                    ups,
+                    octave: octave + (step - nominal - ups).div_euclid(divisions),
+                })
+            })
+            .min_by_key(|(cost, _)| *cost)
+            .unwrap().1
+    }
+
+    // frequency of a step of an octave, A4 being at the reference
+    pub fn frequency(&self, octave: i64, step: i64, reference: f64) -> f64 {
+        let a4 = 4 * self.divisions as i64 + self.letter_step(A_LETTER);
+        let steps = octave * self.divisions as i64 + step - a4;
+        reference * 2f64.powf(steps as f64 / self.divisions as f64)
+    }
+
+    // every note from A0 to the last step of the ninth octave, as Pitch::all_notes. the midi
+    // number of a note is the one of its natural note with its whole sharps or flats
+    pub fn notes(&self, reference: f64) -> SeqData<Pitch> {
+        let mut notes = SeqData::new();
+        let a0 = self.letter_step(A_LETTER);
+        for octave in 0..OCTAVES {
+            for step in 0..self.divisions as i64 {
+                if octave == 0 && step < a0 {
+                    continue;
+                }
+                let spelling = self.spell(octave, step);
+                let name = format!("{}{}{}{}",
+                    LETTERS[spelling.letter],
+                    accidental_name(spelling.half_sharps),
+                    if spelling.ups > 0 { "^" } else { "v" }.repeat(spelling.ups.unsigned_abs() as usize),
+                    spelling.octave);
+                let midi = 12 * (spelling.octave + 1) + LETTER_SEMITONES[spelling.letter] + spelling.half_sharps / 2;
+                notes.add(&name, Pitch::new(&name, self.frequency(octave, step, reference), midi.max(0) as usize));
+            }
+        }
+        notes
+    }
+}
+
+impl FromStr for EqualDivision {
+    type Err = String;
+
+    // the number of steps, with the accidentals naming them as in 24:quarter-tones
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (divisions, accidentals) = match s.split_once(':') {
+            Some((divisions, accidentals)) => (divisions, accidentals.parse()?),
+            None => (s, Accidentals::default()),
+        };
+        match divisions.parse::<usize>() {
+            Ok(divisions) if (MIN_DIVISIONS..=MAX_DIVISIONS).contains(&divisions) => Ok(EqualDivision { divisions, accidentals }),
+            _ => Err(format!("the octave must be divided in {} to {} steps, not '{}'", MIN_DIVISIONS, MAX_DIVISIONS, divisions)),
+        }
+    }
+}
+
+impl Display for EqualDivision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.divisions, self.accidentals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(edo: &str, octave: &str) -> Vec<String> {
+        let notes = edo.parse::<EqualDivision>().unwrap().notes(440.0);
+        notes.iter().filter(|pitch| pitch.name().ends_with(octave)).map(|pitch| pitch.name().to_string()).collect()
+    }
+
+    #[test]
+    fn test_twelve_tone() {
+        // twelve steps are the usual notes
+        let notes = "12".parse::<EqualDivision>().unwrap().notes(440.0);
+        let all_notes: Vec<Pitch> = Pitch::all_notes().iter().cloned().collect();
+        assert_eq!(all_notes.len(), notes.iter().count());
+        for (note, pitch) in notes.iter().zip(&all_notes) {
+            assert_eq!((pitch.name(), pitch.midi()), (note.name(), note.midi()));
+            assert!((note.frequency() - pitch.frequency()).abs() < 1e-9, "{}", note.name());
+        }
+        assert_eq!(vec!["C4", "Db4", "D4", "Eb4", "E4", "F4", "Gb4", "G4", "Ab4", "A4", "Bb4", "B4"], names("12:flats", "4"));
+    }
+
+    #[test]
+    fn test_quarter_tones() {
+        assert_eq!(
+            vec!["C4", "C+4", "C#4", "Dd4", "D4", "D+4", "D#4", "Ed4", "E4", "E+4", "F4", "F+4",
+                "F#4", "Gd4", "G4", "G+4", "G#4", "Ad4", "A4", "A+4", "A#4", "Bd4", "B4", "B+4"],
+            names("24:quarter-tones", "4"));
+        // the neutral third of maqam rast, a quarter tone below E4
+        let notes = "24:quarter-tones".parse::<EqualDivision>().unwrap().notes(440.0);
+        let ed4 = notes.get("Ed4").unwrap();
+        assert!((ed4.frequency() - 440.0 * 2f64.powf(-11.0 / 24.0)).abs() < 1e-9);
+        assert_eq!(64, ed4.midi());
+        // without halves, the steps between are ups and downs
+        assert_eq!("C^4", names("24", "4")[1]);
+    }
+
+    #[test]
+    fn test_divisions() {
+        // a sharp is a single step of 19, so E# and Fb are notes of their own
+        let sharps = names("19", "4");
+        assert_eq!(19, sharps.len());
+        assert!(sharps.contains(&String::from("E#4")) && sharps.contains(&String::from("B#4")));
+        // the Cb just below C5 belongs to the fifth octave
+        let flats = names("19:flats", "5");
+        assert_eq!(Some(&String::from("Cb5")), flats.first());
+
+        // a sharp is two steps of 31 and five of 53. the step below C4 is its half flat rather
+        // than the B#3 a whole sharp above B3
+        assert_eq!(vec!["Cd4", "C4", "C+4", "C#4", "Db4", "Dd4", "D4"], names("31:quarter-tones", "4")[..7].to_vec());
+        let names_53 = names("53", "4");
+        assert_eq!(53, names_53.len());
+        assert_eq!(vec!["Cbv4", "Cb4", "Cv4", "C4", "Dbv4", "Db4", "C#4"], names_53[..7].to_vec());
+        // B# is a comma, a single step, above C
+        let notes = "53".parse::<EqualDivision>().unwrap().notes(440.0);
+        assert_eq!(1, notes.iter().position(|pitch| pitch.name() == "B#3").unwrap() - notes.iter().position(|pitch| pitch.name() == "C4").unwrap());
+
+        for edo in ["4", "73", "twelve", "24:quarters"] {
+            assert!(edo.parse::<EqualDivision>().is_err(), "{}", edo);
+        }
+        assert_eq!("31:quarter-tones", "31:quarter-tones".parse::<EqualDivision>().unwrap().to_string());
+    }
+}
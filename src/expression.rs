@@ -0,0 +1,143 @@
+use serde::{Serialize, Deserialize};
+
+use crate::detector::{nsdf, parabolic_lag};
+
+// range of vibrato rates searched, in Hz
+const MIN_VIBRATO_RATE: f64 = 3.0;
+const MAX_VIBRATO_RATE: f64 = 10.0;
+// periodicity the pitch must show to be a vibrato rather than wandering intonation
+const MIN_VIBRATO_CLARITY: f64 = 0.5;
+// smallest vibrato reported, in cents either side of the note
+const MIN_VIBRATO_EXTENT: f64 = 5.0;
+// distance from the held pitch a glide has to start or end at, in cents
+const MIN_GLIDE_CENTS: f64 = 20.0;
+
+// periodic oscillation of the pitch around the note
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vibrato {
+    // oscillations per second
+    pub rate: f64,
+    // in cents either side of the mean pitch
+    pub extent: f64,
+}
+
+// slide of the pitch into or out of the held note
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Glide {
+    // distance from the held pitch at the far end of the glide, negative when below it
+    pub cents: f64,
+    // in seconds
+    pub duration: f64,
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted[sorted.len() / 2]
+}
+
+// the pitch of a note, one value in cents every hop, with its linear trend removed
+fn detrend(cents: &[f64]) -> Vec<f64> {
+    let n = cents.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = cents.iter().sum::<f64>() / n;
+    let covariance: f64 = cents.iter().enumerate().map(|(x, y)| (x as f64 - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = (0..cents.len()).map(|x| (x as f64 - mean_x).powi(2)).sum();
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+    cents.iter().enumerate().map(|(x, y)| y - mean_y - slope * (x as f64 - mean_x)).collect()
+}
+
+// vibrato of a pitch contour sampled every hop_length seconds, found as the period of its
+// autocorrelation. the contour must hold at least two periods
+pub fn vibrato(cents: &[f64], hop_length: f64) -> Option<Vibrato> {
+    let tau_min = (1.0 / (MAX_VIBRATO_RATE * hop_length)).floor().max(2.0) as usize;
+    let tau_max = ((1.0 / (MIN_VIBRATO_RATE * hop_length)).ceil() as usize).min(cents.len() / 2);
+    if tau_max <= tau_min {
+        return None;
+    }
+    let oscillation = detrend(cents);
+    let correlation = nsdf(&oscillation, tau_max);
+    let tau = (tau_min..tau_max).max_by(|a, b| correlation[*a].total_cmp(&correlation[*b]))?;
+    // the correlation has to peak inside the range, not keep growing past it
+    if correlation[tau] < MIN_VIBRATO_CLARITY || correlation[tau] < correlation[tau - 1] || correlation[tau] < correlation[tau + 1] {
+        return None;
+    }
+    // a sine of amplitude a has an RMS of a / sqrt(2)
+    let rms = (oscillation.iter().map(|x| x * x).sum::<f64>() / oscillation.len() as f64).sqrt();
+    let extent = rms * std::f64::consts::SQRT_2;
+    if extent < MIN_VIBRATO_EXTENT {
+        return None;
+    }
+    Some(Vibrato {
+        rate: 1.0 / (parabolic_lag(&correlation, tau) * hop_length),
+        extent
+    })
+}
+
+// glides at the start and at the end of a pitch contour sampled every hop_length seconds.
+// the pitch is held once it comes within MIN_GLIDE_CENTS of its median, plus a tolerance
+// covering the vibrato of the note. a glide shorter than min_duration, the frame length, is the blur of the frames
+// straddling two notes rather than a slide
+pub fn glides(cents: &[f64], hop_length: f64, tolerance: f64, min_duration: f64) -> (Option<Glide>, Option<Glide>) {
+    if cents.is_empty() {
+        return (None, None);
+    }
+    let held = median(cents);
+    let tolerance = tolerance + MIN_GLIDE_CENTS;
+    let glide = |values: &mut dyn Iterator<Item = &f64>| {
+        let values: Vec<f64> = values.cloned().collect();
+        let frames = values.iter().take_while(|value| (*value - held).abs() > tolerance).count();
+        let duration = frames as f64 * hop_length;
+        if frames == values.len() || duration <= min_duration {
+            return None;
+        }
+        Some(Glide {
+            cents: values[0] - held,
+            duration
+        })
+    };
+    (glide(&mut cents.iter()), glide(&mut cents.iter().rev()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_vibrato() {
+        let contour: Vec<f64> = (0..150).map(|k| 10.0 + 30.0 * (2.0 * PI * 5.5 * k as f64 * 0.01).sin()).collect();
+        let vibrato = vibrato(&contour, 0.01).unwrap();
+        assert!((vibrato.rate - 5.5).abs() < 0.1, "{:?}", vibrato);
+        assert!((vibrato.extent - 30.0).abs() < 1.0, "{:?}", vibrato);
+
+        // a slow drift and a steady pitch are not vibratos
+        let drift: Vec<f64> = (0..150).map(|k| k as f64 * 0.2).collect();
+        assert_eq!(None, super::vibrato(&drift, 0.01));
+        assert_eq!(None, super::vibrato(&[3.0; 150], 0.01));
+        // and a single period is not enough to tell
+        assert_eq!(None, super::vibrato(&contour[..20], 0.01));
+    }
+
+    #[test]
+    fn test_glides() {
+        // a scoop up from 80 cents below, then a fall at the end
+        let contour: Vec<f64> = (0..100).map(|k| match k {
+            k if k < 10 => -80.0 + 8.0 * k as f64,
+            k if k >= 90 => -10.0 * (k - 89) as f64,
+            _ => 0.0,
+        }).collect();
+        let (glide_in, glide_out) = glides(&contour, 0.01, 0.0, 0.03);
+        let glide_in = glide_in.unwrap();
+        assert_eq!(-80.0, glide_in.cents);
+        assert!((glide_in.duration - 0.08).abs() < 1e-9);
+        let glide_out = glide_out.unwrap();
+        assert_eq!(-100.0, glide_out.cents);
+        assert!((glide_out.duration - 0.08).abs() < 1e-9);
+
+        // too short to be more than the blur of a frame
+        assert_eq!((None, None), glides(&contour, 0.01, 0.0, 0.1));
+        assert_eq!((None, None), glides(&[0.0; 100], 0.01, 0.0, 0.03));
+    }
+}
@@ -0,0 +1,145 @@
+use std::{fmt::Display, str::FromStr};
+
+// margin between the estimated noise floor and the level opening the gate, in dB
+const NOISE_MARGIN_DB: f64 = 12.0;
+// bounds of an estimated opening level, in dBFS. a recording without any silence would
+// otherwise put its noise floor on the music itself
+const MIN_OPEN_DB: f64 = -70.0;
+const MAX_OPEN_DB: f64 = -30.0;
+// quantile of the frame levels taken as the noise floor
+const NOISE_FLOOR_QUANTILE: f64 = 0.1;
+pub const DEFAULT_HYSTERESIS_DB: f64 = 6.0;
+
+// level opening the gate, estimated from the recording or given in dBFS
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum GateThreshold {
+    #[default]
+    Auto,
+    Fixed(f64),
+}
+
+// decides which frames are loud enough to carry a note. the gate opens above the threshold
+// and only closes once the level falls hysteresis dB below it, so a fading note is not chopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseGate {
+    pub threshold: GateThreshold,
+    pub hysteresis: f64,
+}
+
+impl Default for NoiseGate {
+    fn default() -> Self {
+        NoiseGate {
+            threshold: GateThreshold::default(),
+            hysteresis: DEFAULT_HYSTERESIS_DB
+        }
+    }
+}
+
+// RMS level of a frame in dB relative to full scale, -inf for digital silence
+pub fn rms_dbfs(frame: &[f64]) -> f64 {
+    let rms = (frame.iter().map(|x| x * x).sum::<f64>() / (frame.len() as f64)).sqrt();
+    20.0 * rms.log10()
+}
+
+// the level most frames stay above, which is the background noise when the recording
+// has some silence in it
+pub fn estimate_noise_floor(levels: &[f64]) -> f64 {
+    if levels.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+    let mut sorted = levels.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted[((sorted.len() - 1) as f64 * NOISE_FLOOR_QUANTILE).round() as usize]
+}
+
+impl NoiseGate {
+    // opening and closing levels in dBFS for a recording of the given frame levels
+    pub fn thresholds(&self, levels: &[f64]) -> (f64, f64) {
+        let open = match self.threshold {
+            GateThreshold::Fixed(level) => level,
+            GateThreshold::Auto => (estimate_noise_floor(levels) + NOISE_MARGIN_DB).clamp(MIN_OPEN_DB, MAX_OPEN_DB),
+        };
+        (open, open - self.hysteresis)
+    }
+
+    // whether the gate is open on each frame
+    pub fn apply(&self, levels: &[f64]) -> Vec<bool> {
+        let (open, close) = self.thresholds(levels);
+        let mut is_open = false;
+        levels.iter().map(|level| {
+            is_open = if is_open { *level >= close } else { *level >= open };
+            is_open
+        }).collect()
+    }
+}
+
+impl FromStr for GateThreshold {
+    type Err = String;
+
+    // auto, or the opening level in dBFS
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(GateThreshold::Auto),
+            _ => match s.parse::<f64>() {
+                Ok(level) if level.is_finite() && level <= 0.0 => Ok(GateThreshold::Fixed(level)),
+                _ => Err(format!("invalid gate threshold '{}'", s)),
+            },
+        }
+    }
+}
+
+impl Display for GateThreshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GateThreshold::Auto => write!(f, "auto"),
+            GateThreshold::Fixed(level) => write!(f, "{}", level),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rms_dbfs() {
+        assert_eq!(0.0, rms_dbfs(&[1.0, -1.0, 1.0, -1.0]));
+        assert!((rms_dbfs(&[0.1, -0.1]) + 20.0).abs() < 1e-9);
+        assert_eq!(f64::NEG_INFINITY, rms_dbfs(&[0.0; 16]));
+    }
+
+    #[test]
+    fn test_estimate_noise_floor() {
+        // a quiet recording, two seconds of room noise then music
+        let levels: Vec<f64> = [-72.0; 20].iter().chain([-40.0; 80].iter()).cloned().collect();
+        assert_eq!(-72.0, estimate_noise_floor(&levels));
+
+        let gate = NoiseGate::default();
+        assert_eq!((-60.0, -66.0), gate.thresholds(&levels));
+
+        // without silence the estimate falls back to the upper bound
+        assert_eq!((-30.0, -36.0), gate.thresholds(&[-3.0; 100]));
+        // and digital silence to the lower one
+        assert_eq!((-70.0, -76.0), gate.thresholds(&[f64::NEG_INFINITY; 100]));
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let gate = NoiseGate {
+            threshold: GateThreshold::Fixed(-40.0),
+            hysteresis: 6.0
+        };
+        let levels = [-50.0, -42.0, -38.0, -44.0, -45.0, -47.0, -44.0, -39.0];
+        let expected = [false, false, true, true, true, false, false, true];
+        assert_eq!(expected.to_vec(), gate.apply(&levels));
+    }
+
+    #[test]
+    fn test_parse_gate_threshold() {
+        assert_eq!(Ok(GateThreshold::Auto), "auto".parse());
+        assert_eq!(Ok(GateThreshold::Fixed(-45.5)), "-45.5".parse());
+        assert_eq!("-45.5", GateThreshold::Fixed(-45.5).to_string());
+        assert!("12".parse::<GateThreshold>().is_err());
+        assert!("loud".parse::<GateThreshold>().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{notes::{CENTS_PER_SEMITONE, CENTS_PER_OCTAVE, Pitch}, seqdatastruct::SeqData};
+
+// instrument the recording is played on, its notes are then written as its players read them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Instrument {
+    // concert pitch, without a range
+    #[default]
+    None,
+    // clarinets in Bb, A and Eb
+    Clarinet,
+    AClarinet,
+    EbClarinet,
+    // trumpet in Bb
+    Trumpet,
+    SopranoSax,
+    AltoSax,
+    TenorSax,
+    BaritoneSax,
+    // horn in F
+    Horn,
+    // written an octave above or below the sounding notes
+    Guitar,
+    DoubleBass,
+    Piccolo,
+}
+
+impl Instrument {
+    // semitones the written notes are above the sounding ones
+    pub fn transposition(&self) -> i32 {
+        match self {
+            Instrument::None => 0,
+            Instrument::Clarinet => 2,
+            Instrument::AClarinet => 3,
+            Instrument::EbClarinet => -3,
+            Instrument::Trumpet => 2,
+            Instrument::SopranoSax => 2,
+            Instrument::AltoSax => 9,
+            Instrument::TenorSax => 14,
+            Instrument::BaritoneSax => 21,
+            Instrument::Horn => 7,
+            Instrument::Guitar => 12,
+            Instrument::DoubleBass => 12,
+            Instrument::Piccolo => -12,
+        }
+    }
+
+    // lowest and highest sounding midi notes the instrument plays
+    pub fn range(&self) -> Option<(usize, usize)> {
+        match self {
+            Instrument::None => None,
+            // written E3 to C7
+            Instrument::Clarinet => Some((50, 94)),
+            Instrument::AClarinet => Some((49, 93)),
+            // written E3 to G6
+            Instrument::EbClarinet => Some((55, 94)),
+            // written F#3 to D6
+            Instrument::Trumpet => Some((52, 84)),
+            // saxophones are written Bb3 to F6
+            Instrument::SopranoSax => Some((56, 87)),
+            Instrument::AltoSax => Some((49, 80)),
+            Instrument::TenorSax => Some((44, 75)),
+            Instrument::BaritoneSax => Some((37, 68)),
+            // written F#2 to C6
+            Instrument::Horn => Some((35, 77)),
+            // open low E to the nineteenth fret of the high E string
+            Instrument::Guitar => Some((40, 83)),
+            Instrument::DoubleBass => Some((28, 67)),
+            // written D4 to C7
+            Instrument::Piccolo => Some((74, 108)),
+        }
+    }
+
+    // whether a sounding note can be played, silences and notes of an instrument without a
+    // range always can
+    pub fn in_range(&self, pitch: &Pitch) -> bool {
+        match self.range() {
+            Some((lowest, highest)) if pitch.frequency() > 0.0 => (lowest..=highest).contains(&pitch.midi()),
+            _ => true,
+        }
+    }
+
+    // the written note of a sounding one, the note of the set nearest to its tempered frequency
+    // once transposed. none for a silence
+    pub fn written(&self, pitch: &Pitch, notes: &SeqData<Pitch>) -> Option<Pitch> {
+        if pitch.frequency() <= 0.0 {
+            return None;
+        }
+        let transposed = pitch.detuned(0.0).frequency() * 2f64.powf(self.transposition() as f64 * CENTS_PER_SEMITONE / CENTS_PER_OCTAVE);
+        Pitch::guess(notes, transposed, None)
+    }
+}
+
+impl FromStr for Instrument {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Instrument::None),
+            "clarinet" => Ok(Instrument::Clarinet),
+            "a-clarinet" => Ok(Instrument::AClarinet),
+            "eb-clarinet" => Ok(Instrument::EbClarinet),
+            "trumpet" => Ok(Instrument::Trumpet),
+            "soprano-sax" => Ok(Instrument::SopranoSax),
+            "alto-sax" => Ok(Instrument::AltoSax),
+            "tenor-sax" => Ok(Instrument::TenorSax),
+            "baritone-sax" => Ok(Instrument::BaritoneSax),
+            "horn" => Ok(Instrument::Horn),
+            "guitar" => Ok(Instrument::Guitar),
+            "double-bass" => Ok(Instrument::DoubleBass),
+            "piccolo" => Ok(Instrument::Piccolo),
+            _ => Err(format!("unknown instrument '{}'", s)),
+        }
+    }
+}
+
+impl Display for Instrument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Instrument::None => "none",
+            Instrument::Clarinet => "clarinet",
+            Instrument::AClarinet => "a-clarinet",
+            Instrument::EbClarinet => "eb-clarinet",
+            Instrument::Trumpet => "trumpet",
+            Instrument::SopranoSax => "soprano-sax",
+            Instrument::AltoSax => "alto-sax",
+            Instrument::TenorSax => "tenor-sax",
+            Instrument::BaritoneSax => "baritone-sax",
+            Instrument::Horn => "horn",
+            Instrument::Guitar => "guitar",
+            Instrument::DoubleBass => "double-bass",
+            Instrument::Piccolo => "piccolo",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_written() {
+        let notes = Pitch::all_notes();
+        let written = |instrument: Instrument, name: &str| instrument.written(notes.get(name).unwrap(), &notes).unwrap().name().to_string();
+        assert_eq!("C5", written(Instrument::Clarinet, "A#4"));
+        assert_eq!("C5", written(Instrument::AClarinet, "A4"));
+        assert_eq!("C4", written(Instrument::EbClarinet, "D#4"));
+        assert_eq!("C5", written(Instrument::AltoSax, "D#4"));
+        assert_eq!("D5", written(Instrument::TenorSax, "C4"));
+        assert_eq!("C4", written(Instrument::Horn, "F3"));
+        assert_eq!("E3", written(Instrument::Guitar, "E2"));
+        assert_eq!("D5", written(Instrument::Piccolo, "D6"));
+        assert_eq!("A4", written(Instrument::None, "A4"));
+
+        // the written note of an out of tune one is the transposed note it is played for
+        let sharp = Pitch::guess(&notes, 452.0, None).unwrap();
+        assert_eq!("B4", Instrument::Trumpet.written(&sharp, &notes).unwrap().name());
+        assert!(Instrument::Trumpet.written(&Pitch::silence(), &notes).is_none());
+    }
+
+    #[test]
+    fn test_range() {
+        let notes = Pitch::all_notes();
+        // the lowest note of a Bb clarinet is a written E3, a sounding D3
+        assert!(Instrument::Clarinet.in_range(notes.get("D3").unwrap()));
+        assert!(!Instrument::Clarinet.in_range(notes.get("C#3").unwrap()));
+        // the highest note of a trumpet is a written D6, a sounding C6
+        assert!(Instrument::Trumpet.in_range(notes.get("C6").unwrap()));
+        assert!(!Instrument::Trumpet.in_range(notes.get("C#6").unwrap()));
+        assert!(!Instrument::Piccolo.in_range(notes.get("C5").unwrap()));
+        assert!(Instrument::Piccolo.in_range(&Pitch::silence()));
+        assert!(Instrument::None.in_range(notes.get("A0").unwrap()));
+
+        for instrument in ["clarinet", "a-clarinet", "alto-sax", "horn", "guitar", "piccolo", "none"] {
+            assert_eq!(instrument, instrument.parse::<Instrument>().unwrap().to_string());
+        }
+        assert!("kazoo".parse::<Instrument>().is_err());
+    }
+}
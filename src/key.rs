@@ -0,0 +1,266 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Serialize, Deserialize};
+
+use crate::notes::{PhiNote, Pitch, SEMITONES_PER_OCTAVE};
+
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+// pitch class of every letter without accidental
+const NATURALS: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+// usual spelling of the tonic of every key, the one with the fewest accidentals in its signature
+const MAJOR_TONICS: [&str; SEMITONES_PER_OCTAVE] = ["C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
+const MINOR_TONICS: [&str; SEMITONES_PER_OCTAVE] = ["C", "C#", "D", "Eb", "E", "F", "F#", "G", "G#", "A", "Bb", "B"];
+
+// weight of every degree of the chromatic scale in a key, from the tonic
+const KRUMHANSL_MAJOR: [f64; SEMITONES_PER_OCTAVE] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const KRUMHANSL_MINOR: [f64; SEMITONES_PER_OCTAVE] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+const TEMPERLEY_MAJOR: [f64; SEMITONES_PER_OCTAVE] = [5.0, 2.0, 3.5, 2.0, 4.5, 4.0, 2.0, 4.5, 2.0, 3.5, 1.5, 4.0];
+const TEMPERLEY_MINOR: [f64; SEMITONES_PER_OCTAVE] = [5.0, 2.0, 3.5, 4.5, 2.0, 4.0, 2.0, 4.5, 3.5, 2.0, 1.5, 4.0];
+
+// key profiles the pitch classes played are correlated with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyProfile {
+    // no key estimation, notes keep their sharp names
+    #[default]
+    None,
+    // probe tone ratings of listeners (Krumhansl and Kessler, 1982)
+    Krumhansl,
+    // frequencies of the degrees in a corpus of classical excerpts (Temperley, 1999)
+    Temperley,
+}
+
+impl KeyProfile {
+    fn profiles(&self) -> Option<([f64; SEMITONES_PER_OCTAVE], [f64; SEMITONES_PER_OCTAVE])> {
+        match self {
+            KeyProfile::None => None,
+            KeyProfile::Krumhansl => Some((KRUMHANSL_MAJOR, KRUMHANSL_MINOR)),
+            KeyProfile::Temperley => Some((TEMPERLEY_MAJOR, TEMPERLEY_MINOR)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+impl Mode {
+    // semitones of the degrees above the tonic, natural minor for the minor mode
+    fn scale(&self) -> [usize; 7] {
+        match self {
+            Mode::Major => [0, 2, 4, 5, 7, 9, 11],
+            Mode::Minor => [0, 2, 3, 5, 7, 8, 10],
+        }
+    }
+}
+
+// the tonic is a pitch class, counted in semitones from C
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    pub tonic: usize,
+    pub mode: Mode,
+}
+
+// letter and accidental of a spelled pitch class, the accidental in semitones
+fn parse_spelling(name: &str) -> (usize, i32) {
+    let letter = LETTERS.iter().position(|c| name.starts_with(*c)).unwrap();
+    let accidental = name.chars().skip(1).map(|c| if c == '#' { 1 } else { -1 }).sum();
+    (letter, accidental)
+}
+
+fn format_spelling(letter: usize, accidental: i32) -> String {
+    let sign = if accidental > 0 { "#" } else { "b" };
+    format!("{}{}", LETTERS[letter], sign.repeat(accidental.unsigned_abs() as usize))
+}
+
+impl Key {
+    pub fn tonic_name(&self) -> &'static str {
+        match self.mode {
+            Mode::Major => MAJOR_TONICS[self.tonic],
+            Mode::Minor => MINOR_TONICS[self.tonic],
+        }
+    }
+
+    // letter and accidental of every degree of the scale
+    fn degrees(&self) -> Vec<(usize, i32)> {
+        let (tonic_letter, _) = parse_spelling(self.tonic_name());
+        self.mode.scale().iter().enumerate().map(|(i, interval)| {
+            let letter = (tonic_letter + i) % LETTERS.len();
+            let pitch_class = (self.tonic + interval) % SEMITONES_PER_OCTAVE;
+            // between -6 and 5, the accidental is never further than two semitones
+            let accidental = (pitch_class as i32 - NATURALS[letter] as i32 + 6).rem_euclid(12) - 6;
+            (letter, accidental)
+        }).collect()
+    }
+
+    // letter and accidental of a pitch class in this key. the degrees of the scale, and the leading
+    // tone of a minor key, are spelled on their own letter, the other notes with the accidentals
+    // of the key signature
+    fn spelling(&self, pitch_class: usize) -> (usize, i32) {
+        let degrees = self.degrees();
+        let sounds = |(letter, accidental): (usize, i32)| (NATURALS[letter] as i32 + accidental).rem_euclid(12) as usize;
+        if let Some(degree) = degrees.iter().find(|degree| sounds(**degree) == pitch_class) {
+            return *degree;
+        }
+        if self.mode == Mode::Minor && pitch_class == (self.tonic + 11) % SEMITONES_PER_OCTAVE {
+            let (letter, accidental) = degrees[6];
+            return (letter, accidental + 1);
+        }
+        let flats = degrees.iter().any(|(_, accidental)| *accidental < 0);
+        match NATURALS.iter().position(|natural| *natural == pitch_class) {
+            Some(letter) => (letter, 0),
+            None if flats => (NATURALS.iter().position(|natural| *natural == pitch_class + 1).unwrap(), -1),
+            None => (NATURALS.iter().position(|natural| *natural + 1 == pitch_class).unwrap(), 1),
+        }
+    }
+
+    // name of a pitch class spelled in this key, such as Bb or E#
+    pub fn spell(&self, pitch_class: usize) -> String {
+        let (letter, accidental) = self.spelling(pitch_class % SEMITONES_PER_OCTAVE);
+        format_spelling(letter, accidental)
+    }
+
+    // full name of a note spelled in this key. the octave follows the letter, so the B of
+    // the fourth octave is the Cb5 of a key with seven flats
+    pub fn spell_pitch(&self, pitch: &Pitch) -> String {
+        let midi = pitch.midi() as i32;
+        let (letter, accidental) = self.spelling(pitch.midi() % SEMITONES_PER_OCTAVE);
+        let octave = (midi - NATURALS[letter] as i32 - accidental).div_euclid(12) - 1;
+        format!("{}{}", format_spelling(letter, accidental), octave)
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = match self.mode {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        };
+        write!(f, "{} {}", self.tonic_name(), mode)
+    }
+}
+
+// a key the recording may be in, with the correlation of its profile with the notes played
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyCandidate {
+    // the key name, such as Eb major
+    pub key: String,
+    pub tonic: String,
+    pub mode: Mode,
+    pub correlation: f64,
+}
+
+fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let mean = |x: &[f64]| x.iter().sum::<f64>() / x.len() as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let covariance: f64 = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+    let spread = |x: &[f64], m: f64| x.iter().map(|v| (v - m) * (v - m)).sum::<f64>().sqrt();
+    let norm = spread(a, mean_a) * spread(b, mean_b);
+    if norm > 0.0 { covariance / norm } else { 0.0 }
+}
+
+// the 24 major and minor keys ranked by the correlation of their profile with the time every
+// pitch class is held, the most likely first. empty without any voiced note
+pub fn estimate_keys(notes: &[PhiNote], profile: KeyProfile) -> Vec<(Key, f64)> {
+    let Some((major, minor)) = profile.profiles() else { return vec![] };
+    let mut durations = [0.0; SEMITONES_PER_OCTAVE];
+    for note in notes.iter().filter(|note| note.pitch.frequency() > 0.0) {
+        durations[note.pitch.midi() % SEMITONES_PER_OCTAVE] += note.end - note.start;
+    }
+    if durations.iter().all(|duration| *duration == 0.0) {
+        return vec![];
+    }
+
+    let mut keys: Vec<(Key, f64)> = [(Mode::Major, major), (Mode::Minor, minor)].iter()
+        .flat_map(|(mode, weights)| (0..SEMITONES_PER_OCTAVE).map(move |tonic| {
+            let rotated: Vec<f64> = (0..SEMITONES_PER_OCTAVE)
+                .map(|pc| weights[(pc + SEMITONES_PER_OCTAVE - tonic) % SEMITONES_PER_OCTAVE])
+                .collect();
+            (Key { tonic, mode: *mode }, correlation(&durations, &rotated))
+        }))
+        .collect();
+    keys.sort_by(|a, b| b.1.total_cmp(&a.1));
+    keys
+}
+
+impl FromStr for KeyProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(KeyProfile::None),
+            "krumhansl" => Ok(KeyProfile::Krumhansl),
+            "temperley" => Ok(KeyProfile::Temperley),
+            _ => Err(format!("unknown key profile '{}'", s)),
+        }
+    }
+}
+
+impl Display for KeyProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyProfile::None => write!(f, "none"),
+            KeyProfile::Krumhansl => write!(f, "krumhansl"),
+            KeyProfile::Temperley => write!(f, "temperley"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn melody(names: &[&str]) -> Vec<PhiNote> {
+        names.iter().enumerate()
+            .map(|(i, name)| PhiNote::new(Pitch::from_str(name).unwrap(), i as f64 * 0.5, (i + 1) as f64 * 0.5))
+            .collect()
+    }
+
+    #[test]
+    fn test_estimate_keys() {
+        // an Eb major scale ending on its tonic
+        let notes = melody(&["D#4", "F4", "G4", "G#4", "A#4", "C5", "D5", "D#5", "A#4", "D#4"]);
+        for profile in [KeyProfile::Krumhansl, KeyProfile::Temperley] {
+            let keys = estimate_keys(&notes, profile);
+            assert_eq!(24, keys.len());
+            assert_eq!("Eb major", keys[0].0.to_string());
+            assert!(keys[0].1 > keys[1].1);
+        }
+
+        // an A minor melody with its raised leading tone
+        let notes = melody(&["A3", "C4", "E4", "A4", "G#4", "A4", "E4", "A3"]);
+        assert_eq!("A minor", estimate_keys(&notes, KeyProfile::Krumhansl)[0].0.to_string());
+
+        assert!(estimate_keys(&melody(&["A4"]), KeyProfile::None).is_empty());
+        assert!(estimate_keys(&[PhiNote::new(Pitch::silence(), 0.0, 1.0)], KeyProfile::Krumhansl).is_empty());
+    }
+
+    #[test]
+    fn test_spell() {
+        let key = |tonic, mode| Key { tonic, mode };
+        let spell = |key: Key| (0..12).map(|pc| key.spell(pc)).collect::<Vec<_>>().join(" ");
+        assert_eq!("C C# D D# E F F# G G# A A# B", spell(key(0, Mode::Major)));
+        assert_eq!("C Db D Eb E F Gb G Ab A Bb B", spell(key(3, Mode::Major)));
+        assert_eq!("C C# D D# E E# F# G G# A A# B", spell(key(6, Mode::Major)));
+        // the leading tone of D minor is sharp despite the flat in its signature
+        assert_eq!("C#", key(2, Mode::Minor).spell(1));
+        assert_eq!("F##", key(8, Mode::Minor).spell(7));
+
+        // the octave follows the letter
+        let b3 = Pitch::from_str("B3").unwrap();
+        assert_eq!("B3", key(0, Mode::Major).spell_pitch(&b3));
+        let c4 = Pitch::from_str("C4").unwrap();
+        assert_eq!("B#3", key(1, Mode::Minor).spell_pitch(&c4));
+        assert_eq!("Bb4", key(5, Mode::Major).spell_pitch(&Pitch::from_str("A#4").unwrap()));
+    }
+
+    #[test]
+    fn test_parse_key_profile() {
+        for profile in [KeyProfile::None, KeyProfile::Krumhansl, KeyProfile::Temperley] {
+            assert_eq!(Ok(profile), profile.to_string().parse());
+        }
+        assert!("aarden".parse::<KeyProfile>().is_err());
+    }
+}
@@ -0,0 +1,22 @@
+pub mod analysis;
+pub mod articulation;
+pub mod channels;
+pub mod chords;
+pub mod detector;
+pub mod edo;
+pub mod expression;
+pub mod gate;
+pub mod instrument;
+pub mod key;
+pub mod notes;
+pub mod onset;
+pub mod polyphony;
+pub mod quantize;
+pub mod resample;
+pub mod scala;
+pub mod seqdatastruct;
+pub mod tempo;
+pub mod tracking;
+pub mod tuning;
+pub mod wav;
+pub mod window;
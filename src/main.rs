@@ -1,13 +1,17 @@
 #[macro_use] extern crate rocket;
-mod analysis;
-mod seqdatastruct;
-mod notes;
-mod tuning;
-mod wav;
-use std::io::Result;
-
-use analysis::{Chunk, analyze_chunk};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use melody_recorder::analysis::{AnalysisConfig, Chunk, analyze_wav};
+use melody_recorder::channels::ChannelMode;
+use melody_recorder::chords::ChordSegment;
+use melody_recorder::gate::NoiseGate;
+use melody_recorder::scala::ScalaTuning;
+use melody_recorder::tuning::Tuning;
+use melody_recorder::wav::read_wav;
+use rocket::State;
 use rocket::data::{ToByteUnit};
+use rocket::form::Form;
 use rocket::serde::json::Json;
 use rocket::tokio::io::AsyncReadExt;
 use rocket::{post, data::Data};
@@ -18,40 +22,246 @@ use serde::Serialize;
 #[response(status = 418, content_type = "json")]
 struct Response(&'static str);
 
+#[derive(Responder)]
+enum ApiError {
+    #[response(status = 400)]
+    BadRequest(String),
+    #[response(status = 500)]
+    Internal(String),
+}
+
+// a single result, or one per channel when every channel is analysed separately
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Analysis<T> {
+    Single(T),
+    Channels(Vec<T>),
+}
+
+impl<T> Analysis<T> {
+    fn new(mut results: Vec<T>, channels: ChannelMode) -> Analysis<T> {
+        if channels == ChannelMode::Separate {
+            Analysis::Channels(results)
+        } else {
+            Analysis::Single(results.remove(0))
+        }
+    }
+}
+
 #[get("/")]
 fn index() -> Response {
     Response("Hello, world!")
 }
 
+// most tunings kept at once, uploads past it are refused
+const MAX_TUNINGS: usize = 256;
+
+// tunings uploaded as Scala files, the id of a tuning being its index
+#[derive(Default)]
+struct Tunings(Mutex<Vec<ScalaTuning>>);
+
 #[launch]
 fn rocket() -> _ {
-    rocket::build().mount("/", routes![index, receive_wav_data])
+    rocket::build()
+        .manage(Tunings::default())
+        .mount("/", routes![index, receive_wav_data, receive_chords, upload_tuning])
 }
 
-// receive the data from the http request body
-#[post("/wav_data", data = "<data>")]
-async fn receive_wav_data(data: Data<'_>) -> Result<Json<Chunk>> {
-    // read the base64 encoded data into a buffer
+// parse an optional query parameter, a missing one keeps the default value
+fn parse_param<T: FromStr<Err = String>>(value: Option<&str>, default: T) -> Result<T, ApiError> {
+    match value {
+        Some(value) => value.parse::<T>().map_err(ApiError::BadRequest),
+        None => Ok(default)
+    }
+}
+
+// numbers are parsed by hand so a malformed value is reported rather than ignored
+fn parse_number<T: FromStr>(name: &str, value: Option<&str>, default: T) -> Result<T, ApiError> {
+    Ok(parse_optional_number(name, value)?.unwrap_or(default))
+}
+
+// a number without a default, none when the parameter is left out
+fn parse_optional_number<T: FromStr>(name: &str, value: Option<&str>) -> Result<Option<T>, ApiError> {
+    value.map(|value| value.parse::<T>().map_err(|_| ApiError::BadRequest(format!("invalid {} '{}'", name, value)))).transpose()
+}
+
+fn parse_flag(name: &str, value: Option<&str>, default: bool) -> Result<bool, ApiError> {
+    match value {
+        Some(value) => value.parse::<bool>().map_err(|_| ApiError::BadRequest(format!("invalid {} '{}', expected true or false", name, value))),
+        None => Ok(default)
+    }
+}
+
+// analysis settings of a request
+// channels: mix (default), all, or the index of the channel to analyse
+// detector: fft (default), yin, pyin, autocorrelation, hps or cepstrum
+// interpolation: refinement of the fft peak, none, quadratic, gaussian (default) or phase
+// window: hann (default), hamming, blackman-harris, rectangular, kaiser or kaiser:<beta>
+// a4: frequency A4 is tuned to in Hz, 440 by default, such as 415 for baroque pitch
+// edo: number of equal steps the octave is divided in instead of twelve semitones, optionally naming
+// them with sharps (default), flats or quarter-tones as in 24:quarter-tones
+// tuning: id of a tuning uploaded to /tunings, used in place of a4 and temperament
+// temperament: equal (default), pythagorean, meantone, werckmeister3, vallotti, kirnberger3 or just,
+// laid out from C or from the tonic given as in just:D
+// frame, hop: frame and hop lengths in seconds, 1.0 by default. the hop is at least 1 ms and a frame spans at most 64 hops
+// gate: level opening the noise gate, auto (default) or in dBFS
+// hysteresis: dB below the opening level at which the gate closes again, 6 by default
+// onsets: attacks splitting repeated notes, flux (default), hfc, complex or none
+// tracking: how frames are turned into notes, hmm (default) or none to keep the nearest note of every frame
+// voices: most notes heard at once, 1 (default) for a melody or more for chords
+// contour: true to add the pitch of every frame to the response, false by default
+// chords: true to add the chords played to the response, false by default
+// key: profile estimating the key and spelling the notes in it, krumhansl, temperley or none (default)
+// beats: true to add the tempo and beats to the response and place the notes on them, false by default
+// bpm: tempo the recording was played at, from 40 to 240, tracked from the attacks by default
+// quantize: true to write the notes as note values, false by default
+// meter: time signature of the quantized notes, auto (default) or such as 3/4
+// instrument: instrument the notes are written for and checked against the range of, none (default), clarinet,
+// a-clarinet, eb-clarinet, trumpet, soprano-sax, alto-sax, tenor-sax, baritone-sax, horn, guitar, double-bass or piccolo
+#[derive(FromForm)]
+struct AnalysisQuery<'r> {
+    channels: Option<&'r str>,
+    detector: Option<&'r str>,
+    interpolation: Option<&'r str>,
+    window: Option<&'r str>,
+    a4: Option<&'r str>,
+    temperament: Option<&'r str>,
+    tuning: Option<&'r str>,
+    edo: Option<&'r str>,
+    frame: Option<&'r str>,
+    hop: Option<&'r str>,
+    gate: Option<&'r str>,
+    hysteresis: Option<&'r str>,
+    onsets: Option<&'r str>,
+    tracking: Option<&'r str>,
+    voices: Option<&'r str>,
+    contour: Option<&'r str>,
+    chords: Option<&'r str>,
+    key: Option<&'r str>,
+    beats: Option<&'r str>,
+    bpm: Option<&'r str>,
+    quantize: Option<&'r str>,
+    meter: Option<&'r str>,
+    instrument: Option<&'r str>,
+}
+
+impl AnalysisQuery<'_> {
+    fn config(&self, tunings: &Tunings) -> Result<AnalysisConfig, ApiError> {
+        let default = AnalysisConfig::default();
+        let scala = match self.tuning {
+            Some(id) => {
+                let id: usize = parse_number("tuning", Some(id), 0)?;
+                let tunings = tunings.0.lock().map_err(|e| ApiError::Internal(e.to_string()))?;
+                Some(tunings.get(id).cloned().ok_or_else(|| ApiError::BadRequest(format!("unknown tuning {}", id)))?)
+            },
+            None => None
+        };
+        Ok(AnalysisConfig {
+            channels: parse_param(self.channels, default.channels)?,
+            detector: parse_param(self.detector, default.detector)?,
+            interpolation: parse_param(self.interpolation, default.interpolation)?,
+            window: parse_param(self.window, default.window)?,
+            tuning: Tuning {
+                temperament: parse_param(self.temperament, default.tuning.temperament)?,
+                reference: parse_number("a4", self.a4, default.tuning.reference)?,
+            },
+            scala,
+            edo: self.edo.map(|edo| edo.parse().map_err(ApiError::BadRequest)).transpose()?,
+            frame_length: parse_number("frame", self.frame, default.frame_length)?,
+            hop_length: parse_number("hop", self.hop, default.hop_length)?,
+            gate: NoiseGate {
+                threshold: parse_param(self.gate, default.gate.threshold)?,
+                hysteresis: parse_number("hysteresis", self.hysteresis, default.gate.hysteresis)?,
+            },
+            onsets: parse_param(self.onsets, default.onsets)?,
+            tracking: parse_param(self.tracking, default.tracking)?,
+            voices: parse_number("voices", self.voices, default.voices)?,
+            contour: parse_flag("contour", self.contour, default.contour)?,
+            chords: parse_flag("chords", self.chords, default.chords)?,
+            key: parse_param(self.key, default.key)?,
+            beats: parse_flag("beats", self.beats, default.beats)?,
+            bpm: parse_optional_number("bpm", self.bpm)?,
+            quantize: parse_flag("quantize", self.quantize, default.quantize)?,
+            meter: parse_param(self.meter, default.meter)?,
+            instrument: parse_param(self.instrument, default.instrument)?,
+        })
+    }
+}
+
+// analyze the WAV file of a request body
+async fn analyze_body(data: Data<'_>, config: &AnalysisConfig) -> Result<Vec<Chunk>, ApiError> {
+    // read the WAV file into a buffer
     let mut buffer = Vec::new();
-    data.open(1.mebibytes()).read_to_end(&mut buffer).await?;
+    data.open(1.mebibytes()).read_to_end(&mut buffer).await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
 
     // bad request if buffer is empty
     if buffer.is_empty() {
-        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "empty buffer"));
+        return Err(ApiError::BadRequest(String::from("empty buffer")));
     }
 
-    // analyze the buffer
-    let chunk = analyze_chunk(&buffer);
-    Ok(Json(chunk))
+    // parse the RIFF header and analyze the samples it describes
+    let wav = read_wav(&buffer).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    analyze_wav(&wav, config).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+// receive the data from the http request body
+#[post("/wav_data?<query..>", data = "<data>")]
+async fn receive_wav_data(data: Data<'_>, query: AnalysisQuery<'_>, tunings: &State<Tunings>) -> Result<Json<Analysis<Chunk>>, ApiError> {
+    let config = query.config(tunings)?;
+    let chunks = analyze_body(data, &config).await?;
+    Ok(Json(Analysis::new(chunks, config.channels)))
+}
+
+// only the chords of the recording, with the same settings as /wav_data
+#[post("/chords?<query..>", data = "<data>")]
+async fn receive_chords(data: Data<'_>, query: AnalysisQuery<'_>, tunings: &State<Tunings>) -> Result<Json<Analysis<Vec<ChordSegment>>>, ApiError> {
+    let config = AnalysisConfig {
+        chords: true,
+        ..query.config(tunings)?
+    };
+    let chords = analyze_body(data, &config).await?
+        .into_iter()
+        .map(|chunk| chunk.chords.unwrap_or_default())
+        .collect();
+    Ok(Json(Analysis::new(chords, config.channels)))
+}
+
+// a scale and its optional keyboard mapping, as the text of .scl and .kbm files
+#[derive(FromForm)]
+struct TuningUpload<'r> {
+    scl: &'r str,
+    kbm: Option<&'r str>,
+}
+
+#[derive(Serialize)]
+struct UploadedTuning {
+    id: usize,
+}
+
+// keep a tuning for the analyses referencing its id, a tuning uploaded again keeping its id
+#[post("/tunings", data = "<upload>")]
+fn upload_tuning(upload: Form<TuningUpload<'_>>, tunings: &State<Tunings>) -> Result<Json<UploadedTuning>, ApiError> {
+    let tuning = ScalaTuning::parse(upload.scl, upload.kbm).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let mut tunings = tunings.0.lock().map_err(|e| ApiError::Internal(e.to_string()))?;
+    if let Some(id) = tunings.iter().position(|kept| *kept == tuning) {
+        return Ok(Json(UploadedTuning { id }));
+    }
+    if tunings.len() >= MAX_TUNINGS {
+        return Err(ApiError::BadRequest(format!("no more than {} tunings can be kept", MAX_TUNINGS)));
+    }
+    tunings.push(tuning);
+    Ok(Json(UploadedTuning { id: tunings.len() - 1 }))
 }
 
 // add unit test to test the function receive_wav_data
 #[cfg(test)]
 mod tests {
-    use crate::notes::PhiNote;
-    use crate::notes::Pitch;
-    use crate::wav::Oscilator;
-    use crate::wav::generate_wav;
+    use melody_recorder::notes::PhiNote;
+    use melody_recorder::notes::Pitch;
+    use melody_recorder::wav::Oscilator;
+    use melody_recorder::wav::generate_wav;
+    use melody_recorder::wav::to_wav_file;
 
     use super::*;
     use rocket::local::asynchronous::Client;
@@ -60,16 +270,13 @@ mod tests {
     async fn test_receive_wav_data() {
         let client = Client::tracked(rocket()).await.unwrap();
 
-        // generate a signal and write it to a buffer
-        let sine = generate_wav(&PhiNote {
-            pitch: Pitch::from_str("A4").unwrap(),
-            start: 0.0,
-            end: 4.0,
-        }, Oscilator::SINE);
+        // generate a signal and wrap it into a WAV file
+        let sine = generate_wav(&PhiNote::new(Pitch::from_str("A4").unwrap(), 0.0, 4.0), Oscilator::SINE);
+        let file = to_wav_file(&sine, 16, 1, 44100);
 
-        // send the buffer to the server in base64 encoding
+        // send the file to the server
         let response = client.post("/wav_data")
-            .body(sine)
+            .body(file)
             .dispatch()
             .await;
 
@@ -83,7 +290,237 @@ mod tests {
         // assert that the start and end of the note are correct
         assert_eq!(chunk.notes[0].start, 0.0);
         assert_eq!(chunk.notes[0].end, 4.0);
-        
+    }
+
+    #[rocket::async_test]
+    async fn test_receive_wav_data_frames() {
+        let client = Client::tracked(rocket()).await.unwrap();
+
+        // half a second of A4 followed by half a second of E5
+        let mut pcm = generate_wav(&PhiNote::new(Pitch::from_str("A4").unwrap(), 0.0, 0.5), Oscilator::SINE);
+        pcm.append(&mut generate_wav(&PhiNote::new(Pitch::from_str("E5").unwrap(), 0.5, 1.0), Oscilator::SINE));
+
+        let response = client.post("/wav_data?frame=0.06&hop=0.02&window=blackman-harris")
+            .body(to_wav_file(&pcm, 16, 1, 44100))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::Ok);
+        let chunk: Chunk = response.into_json().await.unwrap();
+        assert_eq!(chunk.notes.len(), 2);
+        assert_eq!(chunk.notes[0].pitch.name(), "A4");
+        assert_eq!(chunk.notes[1].pitch.name(), "E5");
+        assert_eq!(chunk.notes[1].start, 0.5);
+        assert_eq!(chunk.notes[1].end, 1.0);
+        assert!(chunk.contour.is_none());
+
+        // the same analysis with the pitch of every frame
+        let response = client.post("/wav_data?frame=0.06&hop=0.02&window=blackman-harris&contour=true")
+            .body(to_wav_file(&pcm, 16, 1, 44100))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::Ok);
+        let chunk: Chunk = response.into_json().await.unwrap();
+        assert_eq!(chunk.notes.len(), 2);
+        let contour = chunk.contour.unwrap();
+        assert_eq!(48, contour.len());
+        assert_eq!(0.03, contour[0].time);
+        for point in contour {
+            let expected = if point.time < 0.5 { 440.0 } else { 659.26 };
+            // frames straddling both notes aside
+            if (point.time - 0.5).abs() > 0.03 {
+                assert!((point.frequency - expected).abs() < 2.0, "{:?}", point);
+                assert!(point.confidence > 0.0);
+                assert!((point.rms - std::f64::consts::FRAC_1_SQRT_2).abs() < 0.01, "{:?}", point);
+            }
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_receive_wav_data_channels() {
+        let client = Client::tracked(rocket()).await.unwrap();
+
+        // A4 on the left channel, silence on the right one
+        let sine = generate_wav(&PhiNote::new(Pitch::from_str("A4").unwrap(), 0.0, 2.0), Oscilator::SINE);
+        let stereo: Vec<u8> = sine.chunks_exact(2).flat_map(|x| [x[0], x[1], 0, 0]).collect();
+        let file = to_wav_file(&stereo, 16, 2, 44100);
+
+        let response = client.post("/wav_data?channels=all")
+            .body(file.clone())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::Ok);
+        let chunks: Vec<Chunk> = response.into_json().await.unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].notes[0].pitch.name(), "A4");
+        assert_eq!(chunks[1].notes[0].pitch.name(), "S");
+
+        let response = client.post("/wav_data?channels=0&detector=yin")
+            .body(file.clone())
+            .dispatch()
+            .await;
+        let chunk: Chunk = response.into_json().await.unwrap();
+        assert_eq!(chunk.notes[0].pitch.name(), "A4");
+
+        let response = client.post("/wav_data?channels=5")
+            .body(file)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn test_receive_chords() {
+        let client = Client::tracked(rocket()).await.unwrap();
+
+        // a second of C major, then a second of silence
+        let pcm: Vec<u8> = (0..88200).flat_map(|i| {
+            let t = i as f64 / 44100.0;
+            let sample = if t < 1.0 {
+                [261.63, 329.63, 392.0].iter().map(|f| (2.0 * std::f64::consts::PI * f * t).sin()).sum::<f64>() * 0.3
+            } else {
+                0.0
+            };
+            ((sample * i16::MAX as f64) as i16).to_le_bytes()
+        }).collect();
+
+        let response = client.post("/chords?frame=0.2&hop=0.05")
+            .body(to_wav_file(&pcm, 16, 1, 44100))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::Ok);
+        let chords: Vec<ChordSegment> = response.into_json().await.unwrap();
+        let symbols: Vec<&str> = chords.iter().map(|chord| chord.symbol.as_str()).collect();
+        assert_eq!(vec!["C", "N.C."], symbols);
+        assert_eq!(0.0, chords[0].start);
+        assert!((chords[1].start - 1.0).abs() <= 0.1, "{:?}", chords[1]);
+
+        // the chords are added to the notes when asked for
+        let response = client.post("/wav_data?frame=0.2&hop=0.05&chords=true")
+            .body(to_wav_file(&pcm, 16, 1, 44100))
+            .dispatch()
+            .await;
+        let chunk: Chunk = response.into_json().await.unwrap();
+        assert_eq!(Some(chords), chunk.chords);
+    }
+
+    #[rocket::async_test]
+    async fn test_upload_tuning() {
+        let client = Client::tracked(rocket()).await.unwrap();
+
+        // quarter-comma meantone, sent as the files of a multipart form
+        let scl = "! meanquar.scl\n1/4-comma meantone\n12\n76.049\n193.15686\n310.26471\n5/4\n503.42157\n579.47057\n696.57843\n25/16\n889.73529\n1006.84314\n1082.89214\n2/1\n";
+        let body = format!("--boundary\r\nContent-Disposition: form-data; name=\"scl\"; filename=\"meanquar.scl\"\r\nContent-Type: text/plain\r\n\r\n{}\r\n--boundary--\r\n", scl);
+        let upload = || client.post("/tunings")
+            .header(rocket::http::ContentType::new("multipart", "form-data").with_params(("boundary", "boundary")))
+            .body(body.clone())
+            .dispatch();
+        let response = upload().await;
+        assert_eq!(response.status(), rocket::http::Status::Ok);
+        let id = response.into_json::<serde_json::Value>().await.unwrap()["id"].as_u64().unwrap();
+        // the same tuning sent again is not kept twice
+        assert_eq!(id, upload().await.into_json::<serde_json::Value>().await.unwrap()["id"].as_u64().unwrap());
+
+        // its G#4 is a pure major third below C5, 17 cents flat of the tempered one
+        let pcm: Vec<u8> = (0..44100).flat_map(|i| {
+            let sample = (2.0 * std::f64::consts::PI * 411.22 * i as f64 / 44100.0).sin() * 0.5;
+            ((sample * i16::MAX as f64) as i16).to_le_bytes()
+        }).collect();
+        for (query, cents) in [(String::new(), -17.1), (format!("?tuning={}", id), 0.0)] {
+            let response = client.post(format!("/wav_data{}", query))
+                .body(to_wav_file(&pcm, 16, 1, 44100))
+                .dispatch()
+                .await;
+            let chunk: Chunk = response.into_json().await.unwrap();
+            assert_eq!(chunk.notes[0].pitch.name(), "G#4");
+            assert!((chunk.notes[0].cents.mean - cents).abs() < 2.0, "{:?}", chunk.notes[0].cents);
+        }
+
+        // tunings that cannot be read, and ids that were never given
+        let response = client.post("/tunings")
+            .header(rocket::http::ContentType::Form)
+            .body("scl=scale%0A1%0Afifth")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
+        for query in ["tuning=99", "tuning=first"] {
+            let response = client.post(format!("/wav_data?{}", query))
+                .body(to_wav_file(&pcm, 16, 1, 44100))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), rocket::http::Status::BadRequest, "{}", query);
+        }
+
+        // uploads are refused once the store is full, the meantone being the first one kept
+        for cents in 1..=MAX_TUNINGS {
+            let response = client.post("/tunings")
+                .header(rocket::http::ContentType::Form)
+                .body(format!("scl=scale%0A1%0A{}.0", cents))
+                .dispatch()
+                .await;
+            let status = if cents < MAX_TUNINGS { rocket::http::Status::Ok } else { rocket::http::Status::BadRequest };
+            assert_eq!(status, response.status(), "{}", cents);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_receive_wav_data_bad_request() {
+        let client = Client::tracked(rocket()).await.unwrap();
+
+        // raw samples without a RIFF header
+        let response = client.post("/wav_data")
+            .body(vec![0u8; 1024])
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
+
+        // a header claiming 1 Hz would resample a mebibyte into billions of samples
+        let response = client.post("/wav_data")
+            .body(to_wav_file(&vec![0u8; 1 << 20], 16, 1, 1))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
+
+        // frames must be longer than their hop
+        let response = client.post("/wav_data?frame=0.01&hop=0.02")
+            .body(to_wav_file(&[0u8; 1024], 16, 1, 44100))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
+
+        // nor spanning hundreds of thousands of hops
+        let response = client.post("/wav_data?frame=0.5&hop=0.00003")
+            .body(to_wav_file(&[0u8; 1024], 16, 1, 44100))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
+
+        let response = client.post("/wav_data?frame=short")
+            .body(to_wav_file(&[0u8; 1024], 16, 1, 44100))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
+
+        // gate levels are in dBFS, so never above 0, and unknown settings are refused
+        for query in ["gate=6", "gate=loud", "hysteresis=-3", "onsets=energy", "tracking=median", "voices=0", "voices=two", "contour=yes", "chords=maybe", "key=aarden", "beats=1", "bpm=fast", "bpm=-60", "bpm=1e13", "quantize=on", "meter=3", "meter=3/16", "a4=high", "a4=880", "temperament=werckmeister", "temperament=just:H", "edo=100", "edo=24:arabic", "instrument=kazoo"] {
+            let response = client.post(format!("/wav_data?{}", query))
+                .body(to_wav_file(&[0u8; 1024], 16, 1, 44100))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), rocket::http::Status::BadRequest, "{}", query);
+        }
+
+        // unknown pitch detector
+        let response = client.post("/wav_data?detector=crepe")
+            .body(to_wav_file(&[0u8; 1024], 16, 1, 44100))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
+
+        // 12-bit audio is not supported
+        let response = client.post("/wav_data")
+            .body(to_wav_file(&[0u8; 1024], 12, 1, 44100))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), rocket::http::Status::BadRequest);
     }
 }
-    
\ No newline at end of file
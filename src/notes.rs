@@ -2,12 +2,13 @@ use std::fmt::Display;
 
 use serde::{Serialize, Deserialize};
 
-use crate::{tuning::std_tuning, seqdatastruct::SeqData};
+use crate::{articulation::Articulation, expression::{Glide, Vibrato}, quantize::QuantizedNote, tuning::Tuning, seqdatastruct::SeqData};
 
 pub const A4: f64 = 440.0;
 pub const SEMITONES_PER_OCTAVE: usize = 12;
 pub const NOTES_PER_OCTAVE: i32 = 7;
 pub const CENTS_PER_SEMITONE: f64 = 100.0;
+pub const CENTS_PER_OCTAVE: f64 = CENTS_PER_SEMITONE * SEMITONES_PER_OCTAVE as f64;
 pub const NOTE_NAMES: [&str; SEMITONES_PER_OCTAVE] = [
     "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"
 ];
@@ -40,8 +41,12 @@ impl Pitch {
     }
 
     pub fn all_notes() -> SeqData<Pitch> {
-        // call std_tuning to get a map of all notes
-        let mut notes_map = std_tuning();
+        Pitch::tuned_notes(&Tuning::default())
+    }
+
+    // every note from A0, in the temperament of the tuning with A4 at its reference frequency
+    pub fn tuned_notes(tuning: &Tuning) -> SeqData<Pitch> {
+        let notes_map = tuning.table();
         let mut notes = SeqData::new();
 
         for octave in 0..10 {
@@ -61,42 +66,50 @@ impl Pitch {
         notes
     }
 
+    // the nearest note of the set, carrying the measured frequency and its deviation in cents
     pub fn guess(notes: &SeqData<Pitch>, frequency: f64, last_guess: Option<Pitch>) -> Option<Pitch> {
-        let reference = last_guess.unwrap_or_else(|| Pitch::new("A4", A4, 69));
+        // the A4 of the set whatever its tuning, or its lowest note when A4 is left out
+        let reference = last_guess.or_else(|| notes.get("A4").cloned()).or_else(|| notes.iter().next().cloned())?;
         if frequency < reference.frequency {
-            let mut start = notes.iter_backward(&reference.name).unwrap();
+            let start = notes.iter_backward(&reference.name).unwrap();
             let mut last_note = &reference;
-            while let Some(note) = start.next() {
+            for note in start {
                 if frequency > note.frequency {
-                    let mut note_res: Pitch;
-                    if (last_note.frequency - frequency).abs() < (note.frequency - frequency).abs() {
-                        note_res = last_note.clone();
-                    } else {
-                        note_res = note.clone();
-                    }
-                    note_res.frequency = frequency;
-                    return Some(note_res);
+                    return Some(Pitch::nearest(last_note, note, frequency));
                 }
                 last_note = note;
             }
         } else {
-            let mut start = notes.iter_forward(&reference.name).unwrap();
+            let start = notes.iter_forward(&reference.name).unwrap();
             let mut last_note = &reference;
-            while let Some(note) = start.next() {
+            for note in start {
                 if frequency < note.frequency {
-                    let mut note_res: Pitch;
-                    if (last_note.frequency - frequency).abs() < (note.frequency - frequency).abs() {
-                        note_res = last_note.clone();
-                    } else {
-                        note_res = note.clone();
-                    }
-                    note_res.frequency = frequency;
-                    return Some(note_res);
+                    return Some(Pitch::nearest(last_note, note, frequency));
                 }
                 last_note = note;
             }
         }
-        return None
+        None
+    }
+
+    // pick the closest of two notes on a logarithmic scale
+    fn nearest(a: &Pitch, b: &Pitch, frequency: f64) -> Pitch {
+        let cents_a = cents_between(frequency, a.frequency);
+        let cents_b = cents_between(frequency, b.frequency);
+        let (mut note_res, cents) = if cents_a.abs() < cents_b.abs() { (a.clone(), cents_a) } else { (b.clone(), cents_b) };
+        note_res.cents = cents;
+        note_res.frequency = frequency;
+        note_res
+    }
+
+    // the same note, played the given number of cents away from its tempered frequency
+    pub fn detuned(&self, cents: f64) -> Pitch {
+        let reference = self.frequency / 2f64.powf(self.cents / CENTS_PER_OCTAVE);
+        Pitch {
+            frequency: reference * 2f64.powf(cents / CENTS_PER_OCTAVE),
+            cents,
+            ..self.clone()
+        }
     }
 
     pub fn frequency(&self) -> f64 {
@@ -115,6 +128,7 @@ impl Pitch {
         self.midi
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(name: &str) -> Option<Pitch> {
         let notes = Pitch::all_notes();
         notes.get(name).cloned()
@@ -133,11 +147,74 @@ impl Display for Pitch {
     }
 }
 
+// signed distance from a reference frequency, in cents
+pub fn cents_between(frequency: f64, reference: f64) -> f64 {
+    CENTS_PER_OCTAVE * (frequency / reference).log2()
+}
+
+// intonation of a note over its duration, in cents from the tempered note
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CentsStats {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub std_dev: f64,
+}
+
+impl CentsStats {
+    pub fn from_values(values: &[f64]) -> CentsStats {
+        if values.is_empty() {
+            return CentsStats::default();
+        }
+        let count = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / count;
+        let variance = values.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / count;
+        CentsStats {
+            mean,
+            min: values.iter().cloned().fold(f64::INFINITY, f64::min),
+            max: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            std_dev: variance.sqrt()
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct PhiNote {
     pub pitch: Pitch,
     pub start: f64,
     pub end: f64,
+    #[serde(default)]
+    pub cents: CentsStats,
+    // strength of the attack starting the note, 0 for a silence
+    #[serde(default)]
+    pub onset: f64,
+    // how the note is played, none for a plain note or a silence
+    #[serde(default)]
+    pub articulation: Option<Articulation>,
+    #[serde(default)]
+    pub vibrato: Option<Vibrato>,
+    // slides of the pitch into the note and out of it
+    #[serde(default)]
+    pub glide_in: Option<Glide>,
+    #[serde(default)]
+    pub glide_out: Option<Glide>,
+    // name of the note in the key of the recording, once it is estimated
+    #[serde(default)]
+    pub spelling: Option<String>,
+    // start of the note in beats from the first beat, and its length in beats, once the beats are tracked
+    #[serde(default)]
+    pub beat: Option<f64>,
+    #[serde(default)]
+    pub beat_length: Option<f64>,
+    // the note written on the grid of the meter
+    #[serde(default)]
+    pub quantized: Option<QuantizedNote>,
+    // name of the note as the player of a transposing instrument reads it, and whether the
+    // instrument can play it
+    #[serde(default)]
+    pub written: Option<String>,
+    #[serde(default)]
+    pub out_of_range: bool,
 }
 
 impl Display for PhiNote {
@@ -147,6 +224,26 @@ impl Display for PhiNote {
 }
 
 impl PhiNote {
+    pub fn new(pitch: Pitch, start: f64, end: f64) -> PhiNote {
+        PhiNote {
+            pitch,
+            start,
+            end,
+            cents: CentsStats::default(),
+            onset: 0.0,
+            articulation: None,
+            vibrato: None,
+            glide_in: None,
+            glide_out: None,
+            spelling: None,
+            beat: None,
+            beat_length: None,
+            quantized: None,
+            written: None,
+            out_of_range: false
+        }
+    }
+
     pub fn time_offset(&mut self, value: f64) {
         self.start += value;
         self.end += value;
@@ -185,4 +282,59 @@ mod tests {
             assert_eq!(data.1, guessed.unwrap().name);
         }
     }
+
+    #[test]
+    fn test_guess_cents() {
+        let dataset = [
+            (440.0, "A4", 0.0),
+            (445.0, "A4", 19.56),
+            (435.0, "A4", -19.78),
+            (452.0, "A4", 46.58),
+            // closer to A4 in Hz, but to A#4 in cents
+            (453.0, "A#4", -49.59),
+            (28.0, "A0", 31.19),
+        ];
+        let notes = Pitch::all_notes();
+
+        for data in dataset {
+            let guessed = Pitch::guess(&notes, data.0, None).unwrap();
+            assert_eq!(data.1, guessed.name);
+            assert_eq!(data.0, guessed.frequency);
+            assert!((data.2 - guessed.cents).abs() < 0.01, "{} -> {}", data.0, guessed.cents);
+        }
+    }
+
+    #[test]
+    fn test_guess_tuned() {
+        // at baroque pitch, 415 Hz is an A4 in tune and 440 Hz a sharp A#4
+        let notes = Pitch::tuned_notes(&Tuning::equal(415.0));
+        assert_eq!(415.0, notes.get("A4").unwrap().frequency);
+        let guessed = Pitch::guess(&notes, 415.0, None).unwrap();
+        assert_eq!(("A4", 0.0), (guessed.name(), guessed.cents()));
+        let guessed = Pitch::guess(&notes, 440.0, None).unwrap();
+        assert_eq!("A#4", guessed.name());
+        assert!((guessed.cents() - 1.27).abs() < 0.01, "{}", guessed.cents());
+    }
+
+    #[test]
+    fn test_guess_temperament() {
+        // the just minor seventh above C, 9/5 of 264 Hz, is well sharp of the tempered A#4
+        let just = Tuning { temperament: "just:C".parse().unwrap(), ..Tuning::default() };
+        let guessed = Pitch::guess(&Pitch::tuned_notes(&just), 475.2, None).unwrap();
+        assert_eq!("A#4", guessed.name());
+        assert!(guessed.cents().abs() < 1e-9, "{}", guessed.cents());
+        let guessed = Pitch::guess(&Pitch::all_notes(), 475.2, None).unwrap();
+        assert_eq!("A#4", guessed.name());
+        assert!((guessed.cents() - 33.24).abs() < 0.01, "{}", guessed.cents());
+    }
+
+    #[test]
+    fn test_cents_stats() {
+        let stats = CentsStats::from_values(&[10.0, -10.0, 20.0, 20.0]);
+        assert_eq!(10.0, stats.mean);
+        assert_eq!(-10.0, stats.min);
+        assert_eq!(20.0, stats.max);
+        assert!((stats.std_dev - 12.247).abs() < 0.001);
+        assert_eq!(CentsStats::default(), CentsStats::from_values(&[]));
+    }
 }
@@ -0,0 +1,208 @@
+use std::{f64::consts::PI, fmt::Display, str::FromStr};
+
+use rustfft::num_complex::Complex;
+
+use crate::{detector::windowed_spectrum, window::WindowFunction};
+
+// rise of the detection function above its local median needed to report an attack
+pub const ONSET_THRESHOLD: f64 = 0.1;
+// an onset must be the strongest value this long before and after it, in seconds
+pub const PEAK_WINDOW: f64 = 0.03;
+// half length of the window the local median is taken on, in seconds
+const MEDIAN_WINDOW: f64 = 0.1;
+// keeps the relative measures defined on digital silence
+const EPSILON: f64 = 1e-9;
+
+// detection function used to find the attacks of the notes. every function is relative
+// to the energy of the frame so the same threshold fits loud and quiet recordings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnsetFunction {
+    // notes are only split on pitch changes
+    None,
+    // rise of the magnitude spectrum
+    #[default]
+    SpectralFlux,
+    // rise of the energy weighted by frequency, sensitive to percussive attacks
+    HighFrequencyContent,
+    // distance to the spectrum predicted from the two previous frames, which also
+    // catches soft attacks changing only the phase
+    ComplexDomain,
+}
+
+impl OnsetFunction {
+    // one value per frame, peaking where a note starts
+    pub fn detection(&self, frames: &[&[f64]], window: WindowFunction) -> Vec<f64> {
+        if *self == OnsetFunction::None {
+            return vec![0.0; frames.len()];
+        }
+        let spectra: Vec<Vec<Complex<f64>>> = frames.iter()
+            .map(|frame| windowed_spectrum(frame, window))
+            .collect();
+        (0..spectra.len()).map(|k| {
+            let current = &spectra[k];
+            let silence = vec![Complex::new(0.0, 0.0); current.len()];
+            let previous = if k > 0 { &spectra[k - 1] } else { &silence };
+            match self {
+                OnsetFunction::None => 0.0,
+                OnsetFunction::SpectralFlux => spectral_flux(current, previous),
+                OnsetFunction::HighFrequencyContent => high_frequency_content(current, previous),
+                OnsetFunction::ComplexDomain => {
+                    let before = if k > 1 { Some(&spectra[k - 2]) } else { None };
+                    complex_domain(current, previous, before)
+                },
+            }
+        }).collect()
+    }
+}
+
+fn spectral_flux(current: &[Complex<f64>], previous: &[Complex<f64>]) -> f64 {
+    let rise: f64 = current.iter().zip(previous)
+        .map(|(x, y)| (x.norm() - y.norm()).max(0.0))
+        .sum();
+    let total: f64 = current.iter().map(|x| x.norm()).sum();
+    rise / (total + EPSILON)
+}
+
+fn high_frequency_content(current: &[Complex<f64>], previous: &[Complex<f64>]) -> f64 {
+    let hfc = |spectrum: &[Complex<f64>]| spectrum.iter()
+        .enumerate()
+        .map(|(bin, x)| bin as f64 * x.norm_sqr())
+        .sum::<f64>();
+    let (current, previous) = (hfc(current), hfc(previous));
+    if current < EPSILON {
+        return 0.0;
+    }
+    (1.0 - previous / current).max(0.0)
+}
+
+// only the bins gaining energy are counted, so the end of a note is not an onset
+fn complex_domain(current: &[Complex<f64>], previous: &[Complex<f64>], before: Option<&Vec<Complex<f64>>>) -> f64 {
+    let distance: f64 = current.iter().enumerate()
+        .filter(|(bin, x)| x.norm() >= previous[*bin].norm())
+        .map(|(bin, x)| {
+            let prediction = match before {
+                Some(before) => {
+                    let phase = 2.0 * previous[bin].arg() - before[bin].arg();
+                    Complex::from_polar(previous[bin].norm(), phase.rem_euclid(2.0 * PI))
+                },
+                // without a phase advance to extrapolate, only the magnitude is predicted
+                None => Complex::from_polar(previous[bin].norm(), x.arg()),
+            };
+            (x - prediction).norm()
+        })
+        .sum();
+    let total: f64 = current.iter().map(|x| x.norm()).sum();
+    distance / (total + EPSILON)
+}
+
+// frames where an attack starts: local maxima of the detection function standing above the
+// median around them, on frames the noise gate let through
+pub fn pick_onsets(detection: &[f64], is_open: &[bool], hop_length: f64) -> Vec<usize> {
+    let radius = ((PEAK_WINDOW / hop_length).round() as usize).max(1);
+    let median_radius = ((MEDIAN_WINDOW / hop_length).round() as usize).max(1);
+    let len = detection.len();
+    (0..len).filter(|&k| {
+        let value = detection[k];
+        let is_peak = detection[k.saturating_sub(radius)..k].iter().all(|x| *x < value)
+            && detection[k + 1..(k + radius + 1).min(len)].iter().all(|x| *x <= value);
+        is_open[k] && is_peak && value >= median(&detection[k.saturating_sub(median_radius)..(k + median_radius + 1).min(len)]) + ONSET_THRESHOLD
+    }).collect()
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted[sorted.len() / 2]
+}
+
+impl FromStr for OnsetFunction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(OnsetFunction::None),
+            "flux" => Ok(OnsetFunction::SpectralFlux),
+            "hfc" => Ok(OnsetFunction::HighFrequencyContent),
+            "complex" => Ok(OnsetFunction::ComplexDomain),
+            _ => Err(format!("unknown onset function '{}'", s)),
+        }
+    }
+}
+
+impl Display for OnsetFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OnsetFunction::None => write!(f, "none"),
+            OnsetFunction::SpectralFlux => write!(f, "flux"),
+            OnsetFunction::HighFrequencyContent => write!(f, "hfc"),
+            OnsetFunction::ComplexDomain => write!(f, "complex"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::split_frames;
+
+    use super::*;
+
+    const HOP: usize = 441;
+    const FRAME: usize = 2205;
+
+    // a steady tone whose amplitude jumps back up every half second, as a plucked string
+    // repeating the same note
+    fn repeated_notes() -> Vec<f64> {
+        (0..88200).map(|i| {
+            let t = i as f64 / 44100.0;
+            let envelope = (-6.0 * (t % 0.5)).exp();
+            envelope * (2.0 * PI * 330.0 * t).sin() * 0.8
+        }).collect()
+    }
+
+    #[test]
+    fn test_detect_repeated_notes() {
+        let samples = repeated_notes();
+        let frames = split_frames(&samples, FRAME, HOP);
+        let is_open = vec![true; frames.len()];
+        for function in [OnsetFunction::SpectralFlux, OnsetFunction::HighFrequencyContent, OnsetFunction::ComplexDomain] {
+            let detection = function.detection(&frames, WindowFunction::Hann);
+            let onsets = pick_onsets(&detection, &is_open, 0.01);
+            // the first frame is an attack out of silence, then one every 50 frames. the frame
+            // reporting an attack is the first one the attack is well inside of
+            assert_eq!(4, onsets.len(), "{} {:?}", function, onsets);
+            assert_eq!(0, onsets[0]);
+            for (n, onset) in onsets.iter().enumerate().skip(1) {
+                let expected = n * 50 - FRAME / HOP / 2;
+                assert!(onset.abs_diff(expected) <= 2, "{} {:?}", function, onsets);
+            }
+        }
+    }
+
+    #[test]
+    fn test_steady_tone() {
+        let samples: Vec<f64> = (0..44100).map(|i| (2.0 * PI * 330.0 * i as f64 / 44100.0).sin() * 0.8).collect();
+        let frames = split_frames(&samples, FRAME, HOP);
+        let is_open = vec![true; frames.len()];
+        for function in [OnsetFunction::None, OnsetFunction::SpectralFlux, OnsetFunction::HighFrequencyContent, OnsetFunction::ComplexDomain] {
+            let detection = function.detection(&frames, WindowFunction::Hann);
+            let onsets = pick_onsets(&detection, &is_open, 0.01);
+            let expected: Vec<usize> = if function == OnsetFunction::None { vec![] } else { vec![0] };
+            assert_eq!(expected, onsets, "{}", function);
+        }
+    }
+
+    #[test]
+    fn test_gated_onsets() {
+        let detection = [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let is_open = [true, true, true, true, true, true, true, false, true, true];
+        assert_eq!(vec![2], pick_onsets(&detection, &is_open, 0.01));
+    }
+
+    #[test]
+    fn test_parse_onset_function() {
+        for function in [OnsetFunction::None, OnsetFunction::SpectralFlux, OnsetFunction::HighFrequencyContent, OnsetFunction::ComplexDomain] {
+            assert_eq!(Ok(function), function.to_string().parse());
+        }
+        assert!("energy".parse::<OnsetFunction>().is_err());
+    }
+}
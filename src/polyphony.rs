@@ -0,0 +1,145 @@
+use rustfft::{FftPlanner, num_complex::Complex};
+
+use crate::{detector::{MAX_FREQUENCY, MIN_FREQUENCY, parabolic_lag}, notes::{Pitch, cents_between}, window::WindowFunction};
+
+// partials summed into the salience of a candidate
+const HARMONICS: usize = 10;
+// weighting of the partials, which lowers the higher ones and favours low candidates less
+// than a plain sum would (Klapuri, 2006)
+const WEIGHT_ALPHA: f64 = 27.0;
+const WEIGHT_BETA: f64 = 320.0;
+// a partial is searched for within half a semitone of its ideal frequency
+const PARTIAL_SEARCH_RATIO: f64 = 1.029302236643492;
+// zero padding of the frames, for a finer frequency grid
+const PADDING: usize = 4;
+// half width of the main lobe of a window, in bins of the unpadded spectrum
+const MAIN_LOBE_BINS: usize = 2;
+// a further voice must be this salient relative to the first one found in the frame
+const MIN_RELATIVE_SALIENCE: f64 = 0.25;
+
+fn weight(f0: f64, harmonic: usize) -> f64 {
+    (f0 + WEIGHT_ALPHA) / (harmonic as f64 * f0 + WEIGHT_BETA)
+}
+
+// the strongest bin, and its magnitude, within half a semitone of a frequency
+fn partial(spectrum: &[f64], frequency: f64, bin_width: f64) -> Option<(usize, f64)> {
+    let low = (frequency / PARTIAL_SEARCH_RATIO / bin_width).floor() as usize;
+    let high = ((frequency * PARTIAL_SEARCH_RATIO / bin_width).ceil() as usize).min(spectrum.len() - 1);
+    (low..=high).map(|k| (k, spectrum[k])).max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+fn salience(spectrum: &[f64], f0: f64, bin_width: f64) -> f64 {
+    (1..=HARMONICS)
+        .take_while(|h| (*h as f64 * f0 * PARTIAL_SEARCH_RATIO / bin_width) < spectrum.len() as f64)
+        .filter_map(|h| partial(spectrum, h as f64 * f0, bin_width).map(|(_, magnitude)| weight(f0, h) * magnitude))
+        .sum()
+}
+
+// remove the partials of a note from the spectrum. a partial louder than its neighbours is
+// shared with another note, only the part the smooth envelope of the note accounts for is removed
+fn subtract(spectrum: &mut [f64], f0: f64, bin_width: f64) {
+    let partials: Vec<(usize, f64)> = (1..=HARMONICS)
+        .take_while(|h| (*h as f64 * f0 * PARTIAL_SEARCH_RATIO / bin_width) < spectrum.len() as f64)
+        .filter_map(|h| partial(spectrum, h as f64 * f0, bin_width))
+        .collect();
+    let lobe = MAIN_LOBE_BINS * PADDING;
+    let len = spectrum.len();
+    for (i, (k, magnitude)) in partials.iter().enumerate() {
+        if *magnitude <= 0.0 {
+            continue;
+        }
+        let neighbours = &partials[i.saturating_sub(1)..(i + 2).min(partials.len())];
+        let smooth = neighbours.iter().map(|(_, m)| m).sum::<f64>() / neighbours.len() as f64;
+        let remaining = 1.0 - smooth.min(*magnitude) / magnitude;
+        for value in &mut spectrum[k.saturating_sub(lobe)..(k + lobe + 1).min(len)] {
+            *value *= remaining;
+        }
+    }
+}
+
+// the notes sounding together in a frame, found one after the other as the most salient
+// candidate of the spectrum left by the previous ones. every pitch carries the frequency of
+// its first partial
+pub fn multi_pitch(frame: &[f64], sample_rate: u32, window: WindowFunction, notes: &[Pitch], max_voices: usize) -> Vec<Pitch> {
+    let size = frame.len() * PADDING;
+    let mut data: Vec<Complex<f64>> = window.apply(frame).iter()
+        .map(|x| Complex::new(*x, 0.0))
+        .chain(std::iter::repeat(Complex::new(0.0, 0.0)))
+        .take(size)
+        .collect();
+    FftPlanner::new().plan_fft_forward(size).process(&mut data);
+    let mut spectrum: Vec<f64> = data[..size / 2].iter().map(|x| x.norm()).collect();
+    let bin_width = sample_rate as f64 / size as f64;
+
+    let candidates: Vec<&Pitch> = notes.iter()
+        .filter(|note| note.frequency() >= MIN_FREQUENCY && note.frequency() <= MAX_FREQUENCY)
+        .filter(|note| note.frequency() * PARTIAL_SEARCH_RATIO / bin_width < spectrum.len() as f64)
+        .collect();
+    let mut found: Vec<Pitch> = vec![];
+    let mut first_salience = None;
+    while found.len() < max_voices {
+        let best = candidates.iter()
+            .filter(|note| !found.contains(note))
+            .map(|note| (*note, salience(&spectrum, note.frequency(), bin_width)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((note, value)) = best else { break };
+        let first = *first_salience.get_or_insert(value);
+        if value <= 0.0 || value < first * MIN_RELATIVE_SALIENCE {
+            break;
+        }
+        let frequency = match partial(&spectrum, note.frequency(), bin_width) {
+            Some((k, _)) => parabolic_lag(&spectrum, k) * bin_width,
+            None => note.frequency(),
+        };
+        found.push(note.detuned(cents_between(frequency, note.frequency())));
+        subtract(&mut spectrum, note.frequency(), bin_width);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f64::consts::PI;
+
+    // notes with six partials of decreasing amplitude, as a plucked string
+    fn chord(frequencies: &[f64], samples: usize) -> Vec<f64> {
+        (0..samples).map(|i| {
+            let t = i as f64 / 44100.0;
+            frequencies.iter()
+                .flat_map(|f| (1..=6).map(move |h| (2.0 * PI * f * h as f64 * t).sin() / h as f64))
+                .sum::<f64>() * 0.2
+        }).collect()
+    }
+
+    fn names(pitches: &[Pitch]) -> Vec<&str> {
+        let mut names: Vec<&str> = pitches.iter().map(|pitch| pitch.name()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_multi_pitch() {
+        let notes: Vec<Pitch> = Pitch::all_notes().iter().cloned().collect();
+        let frame = chord(&[261.63, 329.63, 392.0], 4410);
+        let found = multi_pitch(&frame, 44100, WindowFunction::Hann, &notes, 6);
+        assert_eq!(vec!["C4", "E4", "G4"], names(&found));
+        for pitch in &found {
+            assert!(pitch.cents().abs() < 5.0, "{} {}", pitch.name(), pitch.cents());
+        }
+
+        // an octave is a note of its own
+        let frame = chord(&[220.0, 440.0], 4410);
+        assert_eq!(vec!["A3", "A4"], names(&multi_pitch(&frame, 44100, WindowFunction::Hann, &notes, 6)));
+
+        // at most the voices asked for, the most salient first
+        let frame = chord(&[261.63, 329.63, 392.0], 4410);
+        assert_eq!(2, multi_pitch(&frame, 44100, WindowFunction::Hann, &notes, 2).len());
+
+        // a single note has no ghost voices
+        let frame = chord(&[196.0], 4410);
+        assert_eq!(vec!["G3"], names(&multi_pitch(&frame, 44100, WindowFunction::Hann, &notes, 6)));
+        assert!(multi_pitch(&[0.0; 4410], 44100, WindowFunction::Hann, &notes, 6).is_empty());
+    }
+}
@@ -0,0 +1,428 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Serialize, Deserialize};
+
+// ticks of a whole note, divisible by every note value down to the triplet thirty-second
+const WHOLE_TICKS: i64 = 192;
+// shortest note value, as the denominator of a whole note
+const MAX_DENOMINATOR: u32 = 32;
+// straight and triplet subdivisions of a beat the notes are quantized on
+const STRAIGHT_DIVISIONS: i64 = 4;
+const TRIPLET_DIVISIONS: i64 = 3;
+// a triplet is only chosen when it is closer than the straight grid by this share of a beat
+const TRIPLET_MARGIN: f64 = 0.02;
+// beats per measure a meter is detected among, the first one winning a tie
+const METER_CANDIDATES: [u32; 2] = [4, 3];
+
+// a note value as written in a score, such as 4 for a quarter, 2. for a dotted half or 8t
+// for an eighth of a triplet
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct NoteValue {
+    pub denominator: u32,
+    pub dots: u32,
+    pub triplet: bool,
+}
+
+impl NoteValue {
+    fn ticks(&self) -> i64 {
+        // every dot adds half of the previous length
+        let base = WHOLE_TICKS / self.denominator as i64;
+        let dotted = base * ((2 << self.dots) - 1) / (1 << self.dots);
+        if self.triplet { dotted * 2 / 3 } else { dotted }
+    }
+
+    // length of the value in whole notes
+    pub fn whole_notes(&self) -> f64 {
+        self.ticks() as f64 / WHOLE_TICKS as f64
+    }
+}
+
+impl Display for NoteValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.denominator, ".".repeat(self.dots as usize))?;
+        if self.triplet {
+            write!(f, "t")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NoteValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let triplet = s.ends_with('t');
+        let value = s.trim_end_matches('t');
+        let dots = value.len() - value.trim_end_matches('.').len();
+        let value = NoteValue {
+            denominator: value.trim_end_matches('.').parse().map_err(|_| format!("invalid note value '{}'", s))?,
+            dots: dots as u32,
+            triplet
+        };
+        let valid = value.denominator.is_power_of_two() && value.denominator <= MAX_DENOMINATOR
+            && value.dots <= 1 && !(value.triplet && value.dots > 0);
+        if valid { Ok(value) } else { Err(format!("invalid note value '{}'", s)) }
+    }
+}
+
+impl From<NoteValue> for String {
+    fn from(value: NoteValue) -> String {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for NoteValue {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+// beats per measure, and the note value of a beat
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub beats: u32,
+    pub unit: u32,
+}
+
+impl TimeSignature {
+    fn beat_ticks(&self) -> i64 {
+        WHOLE_TICKS / self.unit as i64
+    }
+
+    fn measure_ticks(&self) -> i64 {
+        self.beat_ticks() * self.beats as i64
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        TimeSignature { beats: 4, unit: 4 }
+    }
+}
+
+impl FromStr for TimeSignature {
+    type Err = String;
+
+    // such as 3/4 or 6/8
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid time signature '{}'", s);
+        let (beats, unit) = s.split_once('/').ok_or_else(invalid)?;
+        let signature = TimeSignature {
+            beats: beats.parse().map_err(|_| invalid())?,
+            unit: unit.parse().map_err(|_| invalid())?
+        };
+        // a beat must split in straight and triplet subdivisions that are note values, the
+        // sixteenth of an eighth being the shortest one
+        let valid = signature.beats > 0 && signature.unit.is_power_of_two() && signature.unit <= MAX_DENOMINATOR / STRAIGHT_DIVISIONS as u32;
+        if valid { Ok(signature) } else { Err(invalid()) }
+    }
+}
+
+impl Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.beats, self.unit)
+    }
+}
+
+// time signature the notes are written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Meter {
+    // beats per measure found from the accents of the notes, on quarter beats
+    #[default]
+    Auto,
+    Fixed(TimeSignature),
+}
+
+impl FromStr for Meter {
+    type Err = String;
+
+    // auto, or a time signature
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Meter::Auto),
+            _ => s.parse().map(Meter::Fixed).map_err(|_| format!("invalid meter '{}'", s)),
+        }
+    }
+}
+
+impl Display for Meter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Meter::Auto => write!(f, "auto"),
+            Meter::Fixed(signature) => write!(f, "{}", signature),
+        }
+    }
+}
+
+// a note to quantize, placed in beats, with the strength of its attack. rests have no accent
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoteSpan {
+    pub start: f64,
+    pub end: f64,
+    pub accent: Option<f64>,
+}
+
+// a note written on the grid of a time signature
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QuantizedNote {
+    // measure the note starts in, the first beat being in measure 0 even after a pickup
+    pub measure: i64,
+    // start in beats from the bar line of the measure
+    pub beat: f64,
+    // values tied one after the other, the note being split at the bar lines. never empty, a
+    // note shorter than the grid lasting a step of it
+    pub values: Vec<NoteValue>,
+    // quantized minus played time, in seconds
+    pub start_error: f64,
+    pub end_error: f64,
+}
+
+// position in beats snapped to the nearest sixteenth or triplet eighth of a beat, in ticks
+fn snap(position: f64, beat_ticks: i64) -> i64 {
+    let nearest = |divisions: i64| {
+        let step = (beat_ticks / divisions) as f64;
+        let ticks = (position * beat_ticks as f64 / step).round() * step;
+        (ticks as i64, (ticks / beat_ticks as f64 - position).abs())
+    };
+    let (straight, straight_error) = nearest(STRAIGHT_DIVISIONS);
+    let (triplet, triplet_error) = nearest(TRIPLET_DIVISIONS);
+    if triplet_error + TRIPLET_MARGIN < straight_error { triplet } else { straight }
+}
+
+// every note value, the longest first
+fn note_values() -> Vec<NoteValue> {
+    let mut values: Vec<NoteValue> = (0..=MAX_DENOMINATOR.trailing_zeros())
+        .map(|n| 1 << n)
+        .flat_map(|denominator| [(1, false), (0, false), (0, true)].map(|(dots, triplet)| NoteValue { denominator, dots, triplet }))
+        .filter(|value| value.ticks() > 0)
+        .collect();
+    values.sort_by_key(|value| -value.ticks());
+    values
+}
+
+// tied values adding up to exactly a length, as few as possible and the longest first. a long
+// note is filled with the longest value until the rest is short enough to search. empty when
+// no values add up to the length, which never happens between positions of the grid
+pub fn split_note_values(ticks: i64) -> Vec<NoteValue> {
+    let values = note_values();
+    let longest = values[0];
+    let mut left = ticks;
+    let mut result = vec![];
+    while left > 2 * longest.ticks() {
+        result.push(longest);
+        left -= longest.ticks();
+    }
+    if left <= 0 {
+        return result;
+    }
+
+    // fewest values adding up to every length up to the rest, none when they cannot
+    let mut counts: Vec<Option<usize>> = vec![Some(0)];
+    for length in 1..=left {
+        let count = values.iter()
+            .filter(|value| value.ticks() <= length)
+            .filter_map(|value| counts[(length - value.ticks()) as usize])
+            .min();
+        counts.push(count.map(|count| count + 1));
+    }
+    if counts[left as usize].is_none() {
+        return vec![];
+    }
+    while left > 0 {
+        let count = counts[left as usize].unwrap();
+        let value = values.iter()
+            .find(|value| value.ticks() <= left && counts[(left - value.ticks()) as usize] == Some(count - 1))
+            .unwrap();
+        result.push(*value);
+        left -= value.ticks();
+    }
+    result
+}
+
+// beats per measure with the most accented beat, and the beat of the first bar line.
+// every candidate meter is tried on every phase, the accents of the beats falling on its bar
+// lines being compared with the accents of all the beats
+fn detect_meter(accents: &[f64], candidates: &[u32]) -> (u32, usize) {
+    let mean = accents.iter().sum::<f64>() / accents.len().max(1) as f64;
+    let mut best = (candidates[0], 0, 0.0);
+    for beats in candidates {
+        let length = *beats as usize;
+        if accents.len() < 2 * length {
+            continue;
+        }
+        for phase in 0..length {
+            let downbeats: Vec<f64> = accents.iter().skip(phase).step_by(length).cloned().collect();
+            let contrast = downbeats.iter().sum::<f64>() / downbeats.len() as f64 - mean;
+            if contrast > best.2 {
+                best = (*beats, phase, contrast);
+            }
+        }
+    }
+    (best.0, best.1)
+}
+
+// write the notes on the grid of a meter. time gives the seconds of a position in beats,
+// for the quantization errors
+pub fn quantize(notes: &[NoteSpan], meter: Meter, time: impl Fn(f64) -> f64) -> (TimeSignature, Vec<QuantizedNote>) {
+    let beat_ticks = match meter {
+        Meter::Auto => TimeSignature::default().beat_ticks(),
+        Meter::Fixed(signature) => signature.beat_ticks(),
+    };
+    // a note too short for the grid lasts a step of it rather than nothing
+    let spans: Vec<(i64, i64)> = notes.iter()
+        .map(|note| {
+            let start = snap(note.start, beat_ticks);
+            (start, snap(note.end, beat_ticks).max(start + beat_ticks / STRAIGHT_DIVISIONS))
+        })
+        .collect();
+
+    // strongest attack on every beat from the first one
+    let last_beat = spans.iter().map(|(start, _)| start.div_euclid(beat_ticks)).max().unwrap_or(0).max(0);
+    let mut accents = vec![0.0; last_beat as usize + 1];
+    for ((start, _), note) in spans.iter().zip(notes) {
+        if let Some(accent) = note.accent {
+            if *start >= 0 && start % beat_ticks == 0 {
+                let beat = &mut accents[(start / beat_ticks) as usize];
+                *beat = accent.max(*beat);
+            }
+        }
+    }
+    let signature = match meter {
+        Meter::Auto => {
+            let (beats, _) = detect_meter(&accents, &METER_CANDIDATES);
+            TimeSignature { beats, unit: 4 }
+        },
+        Meter::Fixed(signature) => signature,
+    };
+    let (_, phase) = detect_meter(&accents, &[signature.beats]);
+    // the measure holding the first beat starts on a bar line, or before it on a pickup
+    let measure_ticks = signature.measure_ticks();
+    let origin = if phase > 0 { (phase as i64 - signature.beats as i64) * beat_ticks } else { 0 };
+
+    let quantized = spans.iter().zip(notes).map(|((start, end), note)| {
+        let measure = (start - origin).div_euclid(measure_ticks);
+        let mut values = vec![];
+        let mut cursor = *start;
+        while cursor < *end {
+            let bar = origin + ((cursor - origin).div_euclid(measure_ticks) + 1) * measure_ticks;
+            let piece = bar.min(*end) - cursor;
+            values.extend(split_note_values(piece));
+            cursor += piece;
+        }
+        let beats = |ticks: i64| ticks as f64 / beat_ticks as f64;
+        QuantizedNote {
+            measure,
+            beat: beats(start - origin - measure * measure_ticks),
+            values,
+            start_error: time(beats(*start)) - time(note.start),
+            end_error: time(beats(*end)) - time(note.end)
+        }
+    }).collect();
+    (signature, quantized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(s: &str) -> Vec<NoteValue> {
+        s.split(' ').map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_note_values() {
+        for value in ["4", "2.", "8t", "32", "1"] {
+            assert_eq!(value, value.parse::<NoteValue>().unwrap().to_string());
+        }
+        for value in ["3", "64", "4..", "4.t", "t", ""] {
+            assert!(value.parse::<NoteValue>().is_err(), "{}", value);
+        }
+        assert_eq!(0.375, "4.".parse::<NoteValue>().unwrap().whole_notes());
+        assert_eq!(1.0 / 12.0, "8t".parse::<NoteValue>().unwrap().whole_notes());
+
+        assert_eq!(values("4"), split_note_values(48));
+        assert_eq!(values("2."), split_note_values(144));
+        assert_eq!(values("1 4"), split_note_values(240));
+        assert_eq!(values("8t"), split_note_values(16));
+        assert_eq!(values("2 16"), split_note_values(108));
+        // the greedy dotted sixteenth would leave two ticks over
+        assert_eq!(values("8t 32t"), split_note_values(20));
+        assert_eq!(values("1. 1. 1"), split_note_values(768));
+        assert!(split_note_values(2).is_empty());
+    }
+
+    #[test]
+    fn test_split_grid_lengths() {
+        // every length between two positions of the grid of an allowed beat, at least a step
+        // apart as the notes are, is written exactly
+        for unit in [1, 2, 4, 8] {
+            let beat_ticks = TimeSignature { beats: 4, unit }.beat_ticks();
+            let step = beat_ticks / STRAIGHT_DIVISIONS;
+            let positions: Vec<i64> = (0..=8 * beat_ticks)
+                .filter(|ticks| ticks % step == 0 || ticks % (beat_ticks / TRIPLET_DIVISIONS) == 0)
+                .collect();
+            for start in &positions {
+                for end in positions.iter().filter(|end| **end >= start + step) {
+                    let split = split_note_values(end - start);
+                    assert_eq!(end - start, split.iter().map(NoteValue::ticks).sum::<i64>(), "{} in 1/{}", end - start, unit);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_meter() {
+        assert_eq!(Ok(Meter::Auto), "auto".parse());
+        assert_eq!(Ok(Meter::Fixed(TimeSignature { beats: 6, unit: 8 })), "6/8".parse());
+        for meter in ["4", "0/4", "3/5", "3/16", "3/32", "three/4"] {
+            assert!(meter.parse::<Meter>().is_err(), "{}", meter);
+        }
+    }
+
+    #[test]
+    fn test_quantize() {
+        let span = |start, end, accent| NoteSpan { start, end, accent: Some(accent) };
+        // a played quarter, dotted quarter and eighth, then a triplet and a note tied over the bar
+        let notes = [
+            span(0.02, 0.97, 1.0),
+            span(0.97, 2.52, 0.5),
+            span(2.52, 2.98, 0.5),
+            span(3.0, 3.34, 0.5),
+            span(3.34, 3.67, 0.5),
+            span(3.67, 4.0, 0.5),
+            span(4.0, 6.0, 1.0),
+            span(6.0, 7.0, 0.5),
+            NoteSpan { start: 7.0, end: 10.0, accent: None },
+        ];
+        let (signature, quantized) = quantize(&notes, Meter::Auto, |beats| beats * 0.5);
+        assert_eq!(TimeSignature::default(), signature);
+        let written: Vec<String> = quantized.iter()
+            .map(|note| note.values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join("~"))
+            .collect();
+        assert_eq!(vec!["4", "4.", "8", "8t", "8t", "8t", "2", "4", "4~2"], written);
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 1, 1, 1], quantized.iter().map(|note| note.measure).collect::<Vec<_>>());
+        assert_eq!(2.5, quantized[2].beat);
+        assert!((quantized[0].start_error + 0.01).abs() < 1e-9);
+        assert!((quantized[0].end_error - 0.015).abs() < 1e-9);
+
+        // accents every third beat, after a pickup of a beat
+        let notes: Vec<NoteSpan> = (0..12).map(|i| span(i as f64, i as f64 + 1.0, if i % 3 == 1 { 1.0 } else { 0.3 })).collect();
+        let (signature, quantized) = quantize(&notes, Meter::Auto, |beats| beats);
+        assert_eq!(TimeSignature { beats: 3, unit: 4 }, signature);
+        assert_eq!((0, 2.0), (quantized[0].measure, quantized[0].beat));
+        assert_eq!((1, 0.0), (quantized[1].measure, quantized[1].beat));
+
+        // a supplied meter is kept, the bar lines following the accents
+        let (signature, quantized) = quantize(&notes, Meter::Fixed(TimeSignature { beats: 6, unit: 8 }), |beats| beats);
+        assert_eq!(TimeSignature { beats: 6, unit: 8 }, signature);
+        assert_eq!(values("8"), quantized[0].values);
+
+        // a grace note snapping to a single tick is written as the shortest step of the grid
+        let (_, quantized) = quantize(&[span(0.0, 1.0, 1.0), span(1.0, 1.05, 0.5)], Meter::Auto, |beats| beats);
+        assert_eq!(values("16"), quantized[1].values);
+        assert!((quantized[1].end_error - 0.2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,90 @@
+use std::f64::consts::PI;
+
+// number of zero crossings of the sinc kernel kept on each side of a sample
+const ZERO_CROSSINGS: f64 = 32.0;
+// keep the cutoff slightly below the Nyquist frequency to leave room for the transition band
+const ROLLOFF: f64 = 0.95;
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Blackman window over [-1, 1]
+fn blackman(x: f64) -> f64 {
+    0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+}
+
+// band-limited resampling of a single channel using a windowed sinc kernel
+pub fn resample(samples: &[f64], from: u32, to: u32) -> Vec<f64> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = to as f64 / from as f64;
+    // when downsampling the kernel has to filter out everything above the new Nyquist frequency
+    let cutoff = ratio.min(1.0) * ROLLOFF;
+    let half_width = ZERO_CROSSINGS / cutoff;
+    let output_len = (samples.len() as f64 * ratio).floor() as usize;
+    let last_sample = samples.len() - 1;
+
+    (0..output_len).map(|i| {
+        // position of the output sample on the input time line
+        let center = i as f64 / ratio;
+        let first = (center - half_width).ceil().max(0.0) as usize;
+        let last = ((center + half_width).floor() as usize).min(last_sample);
+        (first..=last).map(|j| {
+            let x = j as f64 - center;
+            samples[j] * cutoff * sinc(cutoff * x) * blackman(x / half_width)
+        }).sum()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f64, sample_rate: u32, seconds: f64) -> Vec<f64> {
+        let len = (seconds * sample_rate as f64) as usize;
+        (0..len).map(|i| (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin()).collect()
+    }
+
+    fn rms(samples: &[f64]) -> f64 {
+        (samples.iter().map(|x| x * x).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_resample_identity() {
+        let samples = sine(440.0, 44100, 0.1);
+        assert_eq!(samples, resample(&samples, 44100, 44100));
+        assert!(resample(&[], 48000, 44100).is_empty());
+    }
+
+    #[test]
+    fn test_resample_sine() {
+        for (from, to) in [(48000, 44100), (96000, 44100), (8000, 44100), (22050, 44100), (16000, 44100)] {
+            let resampled = resample(&sine(440.0, from, 0.5), from, to);
+            let expected = sine(440.0, to, 0.5);
+            assert_eq!(expected.len(), resampled.len());
+
+            // ignore the edges where the kernel runs out of input
+            let margin = to as usize / 20;
+            let error: Vec<f64> = resampled[margin..resampled.len() - margin].iter()
+                .zip(&expected[margin..expected.len() - margin])
+                .map(|(a, b)| a - b)
+                .collect();
+            assert!(rms(&error) < 1e-3, "{} -> {}: error {}", from, to, rms(&error));
+        }
+    }
+
+    #[test]
+    fn test_resample_removes_aliases() {
+        // 30kHz cannot be represented at 44.1kHz and must not fold back to 14.1kHz
+        let resampled = resample(&sine(30000.0, 96000, 0.2), 96000, 44100);
+        let margin = 2205;
+        assert!(rms(&resampled[margin..resampled.len() - margin]) < 1e-3);
+    }
+}
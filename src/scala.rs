@@ -0,0 +1,317 @@
+use std::fmt::Display;
+
+use crate::{notes::{A4, CENTS_PER_OCTAVE, Pitch}, seqdatastruct::SeqData, tuning::{A4_MIDI, midi_name}};
+
+// highest key of a keyboard mapping
+const MAX_KEY: usize = 127;
+// the middle C a linear mapping starts the scale from
+const MIDDLE_C: usize = 60;
+
+#[derive(Debug, PartialEq)]
+pub enum ScalaError {
+    Missing(&'static str),
+    Invalid(String),
+}
+
+impl Display for ScalaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScalaError::Missing(what) => write!(f, "missing {} in Scala file", what),
+            ScalaError::Invalid(reason) => write!(f, "invalid Scala file: {}", reason),
+        }
+    }
+}
+
+// the lines of a Scala file that are not comments
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().map(|line| line.trim_end_matches('\r')).filter(|line| !line.starts_with('!'))
+}
+
+// the value of a line is its first word, anything after it is a comment
+fn value<'a>(lines: &mut impl Iterator<Item = &'a str>, what: &'static str) -> Result<&'a str, ScalaError> {
+    lines.map(str::trim).find(|line| !line.is_empty())
+        .and_then(|line| line.split_whitespace().next())
+        .ok_or(ScalaError::Missing(what))
+}
+
+fn number<'a, T: std::str::FromStr>(lines: &mut impl Iterator<Item = &'a str>, what: &'static str) -> Result<T, ScalaError> {
+    let value = value(lines, what)?;
+    value.parse().map_err(|_| ScalaError::Invalid(format!("invalid {} '{}'", what, value)))
+}
+
+// a pitch of a scale in cents above its first note, written in cents when it has a dot and
+// as a ratio such as 3/2 or 2 otherwise
+fn parse_pitch(value: &str) -> Result<f64, ScalaError> {
+    let invalid = || ScalaError::Invalid(format!("invalid pitch '{}'", value));
+    if value.contains('.') {
+        return value.parse::<f64>().ok().filter(|cents| cents.is_finite()).ok_or_else(invalid);
+    }
+    let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+    let numerator: u64 = numerator.parse().map_err(|_| invalid())?;
+    let denominator: u64 = denominator.parse().map_err(|_| invalid())?;
+    if numerator == 0 || denominator == 0 {
+        return Err(invalid());
+    }
+    Ok(CENTS_PER_OCTAVE * (numerator as f64 / denominator as f64).log2())
+}
+
+// a scale of a .scl file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    // every pitch above the first note in cents, the last one being the period the scale
+    // repeats at, usually the octave
+    pub pitches: Vec<f64>,
+}
+
+impl Scale {
+    pub fn parse(text: &str) -> Result<Scale, ScalaError> {
+        let mut lines = lines(text);
+        // the description may be empty, but its line is there
+        let description = lines.next().ok_or(ScalaError::Missing("description"))?.trim().to_string();
+        let count: usize = number(&mut lines, "number of notes")?;
+        let pitches = (0..count)
+            .map(|_| value(&mut lines, "pitch").and_then(parse_pitch))
+            .collect::<Result<Vec<f64>, ScalaError>>()?;
+        match pitches.last() {
+            Some(period) if *period > 0.0 => Ok(Scale { description, pitches }),
+            Some(_) => Err(ScalaError::Invalid(String::from("the scale must repeat at a rising interval"))),
+            None => Err(ScalaError::Invalid(String::from("a scale needs at least one pitch"))),
+        }
+    }
+
+    // cents above the first note of any degree, those past the period in the next periods
+    pub fn cents(&self, degree: i64) -> f64 {
+        let size = self.pitches.len() as i64;
+        let step = degree.rem_euclid(size) as usize;
+        let period = self.pitches[self.pitches.len() - 1];
+        degree.div_euclid(size) as f64 * period + if step == 0 { 0.0 } else { self.pitches[step - 1] }
+    }
+}
+
+// how the keys of a keyboard play the degrees of a scale, as in a .kbm file
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    // range of keys that are played
+    pub first: usize,
+    pub last: usize,
+    // key playing the first entry of the mapping
+    pub middle: usize,
+    // key tuned to the reference frequency
+    pub reference: usize,
+    pub frequency: f64,
+    // degree the mapping repeats at
+    pub octave_degree: usize,
+    // keys the mapping repeats after, the keys follow the degrees of the scale one by one when 0
+    pub size: usize,
+    // degree played by each key from the middle one, none for a key left out. keys past its
+    // end, when the file stops short of the size, are left out too
+    pub mapping: Vec<Option<usize>>,
+}
+
+impl Default for KeyboardMapping {
+    // every key plays the next degree, the scale starting from middle C with A4 at 440 Hz
+    fn default() -> Self {
+        KeyboardMapping {
+            first: 0,
+            last: MAX_KEY,
+            middle: MIDDLE_C,
+            reference: A4_MIDI,
+            frequency: A4,
+            octave_degree: 0,
+            size: 0,
+            mapping: vec![],
+        }
+    }
+}
+
+impl KeyboardMapping {
+    pub fn parse(text: &str) -> Result<KeyboardMapping, ScalaError> {
+        let mut lines = lines(text);
+        let size: usize = number(&mut lines, "map size")?;
+        if size > MAX_KEY + 1 {
+            return Err(ScalaError::Invalid(format!("a mapping has at most {} keys", MAX_KEY + 1)));
+        }
+        let first = number(&mut lines, "first note")?;
+        let last = number(&mut lines, "last note")?;
+        let middle = number(&mut lines, "middle note")?;
+        let reference = number(&mut lines, "reference note")?;
+        let frequency: f64 = number(&mut lines, "reference frequency")?;
+        let octave_degree = number(&mut lines, "formal octave degree")?;
+        // the mapping may stop before its size, the keys missing at its end being left out
+        let mut mapping = vec![];
+        while mapping.len() < size {
+            match value(&mut lines, "mapping") {
+                Ok("x") => mapping.push(None),
+                Ok(degree) => mapping.push(Some(degree.parse().map_err(|_| ScalaError::Invalid(format!("invalid degree '{}'", degree)))?)),
+                Err(_) => break,
+            }
+        }
+
+        if first > last || last > MAX_KEY || middle > MAX_KEY || reference > MAX_KEY {
+            return Err(ScalaError::Invalid(format!("keys must be between 0 and {}", MAX_KEY)));
+        }
+        if !(frequency.is_finite() && frequency > 0.0) {
+            return Err(ScalaError::Invalid(format!("invalid reference frequency {}", frequency)));
+        }
+        Ok(KeyboardMapping { first, last, middle, reference, frequency, octave_degree, size, mapping })
+    }
+
+    // degree of the scale a key plays, none when it is left out
+    fn degree(&self, scale: &Scale, key: usize) -> Option<i64> {
+        let offset = key as i64 - self.middle as i64;
+        if self.size == 0 {
+            return Some(offset);
+        }
+        let size = self.size as i64;
+        let degree = (*self.mapping.get(offset.rem_euclid(size) as usize)?)?;
+        let octave_degree = if self.octave_degree == 0 { scale.pitches.len() } else { self.octave_degree };
+        Some(degree as i64 + offset.div_euclid(size) * octave_degree as i64)
+    }
+}
+
+// a tuning read from Scala files, a scale played on a keyboard mapping
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalaTuning {
+    pub scale: Scale,
+    pub keyboard: KeyboardMapping,
+}
+
+impl ScalaTuning {
+    pub fn new(scale: Scale, keyboard: KeyboardMapping) -> Result<ScalaTuning, ScalaError> {
+        let degrees = scale.pitches.len();
+        if keyboard.octave_degree > degrees || keyboard.mapping.iter().flatten().any(|degree| *degree > degrees) {
+            return Err(ScalaError::Invalid(format!("degrees of the mapping must be between 0 and {}", degrees)));
+        }
+        if keyboard.degree(&scale, keyboard.reference).is_none() {
+            return Err(ScalaError::Invalid(String::from("the reference note is left out of the mapping")));
+        }
+        if (keyboard.first..=keyboard.last).all(|key| keyboard.degree(&scale, key).is_none()) {
+            return Err(ScalaError::Invalid(String::from("every key of the range is left out of the mapping")));
+        }
+        Ok(ScalaTuning { scale, keyboard })
+    }
+
+    // a .scl file, with the keyboard mapping of a .kbm file or every key playing the next degree
+    pub fn parse(scl: &str, kbm: Option<&str>) -> Result<ScalaTuning, ScalaError> {
+        let keyboard = kbm.map(KeyboardMapping::parse).transpose()?.unwrap_or_default();
+        ScalaTuning::new(Scale::parse(scl)?, keyboard)
+    }
+
+    // frequency of a key, none when it is not played
+    pub fn frequency(&self, key: usize) -> Option<f64> {
+        if !(self.keyboard.first..=self.keyboard.last).contains(&key) {
+            return None;
+        }
+        let degree = self.keyboard.degree(&self.scale, key)?;
+        let reference = self.keyboard.degree(&self.scale, self.keyboard.reference)?;
+        let cents = self.scale.cents(degree) - self.scale.cents(reference);
+        Some(self.keyboard.frequency * 2f64.powf(cents / CENTS_PER_OCTAVE))
+    }
+
+    // every key played, named after its midi note and sorted by frequency as Pitch::all_notes
+    pub fn notes(&self) -> SeqData<Pitch> {
+        let mut pitches: Vec<Pitch> = (self.keyboard.first..=self.keyboard.last)
+            .filter_map(|key| self.frequency(key).map(|frequency| Pitch::new(&midi_name(key), frequency, key)))
+            .collect();
+        pitches.sort_by(|a, b| a.frequency().total_cmp(&b.frequency()));
+        let mut notes = SeqData::new();
+        for pitch in pitches {
+            let name = pitch.name().to_string();
+            notes.add(&name, pitch);
+        }
+        notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEANTONE: &str = "! meanquar.scl
+!
+1/4-comma meantone scale. Pietro Aaron's temperament (1523)
+ 12
+!
+ 76.04900
+ 193.15686
+ 310.26471
+ 5/4
+ 503.42157
+ 579.47057
+ 696.57843
+ 25/16
+ 889.73529
+ 1006.84314
+ 1082.89214
+ 2/1
+";
+
+    #[test]
+    fn test_parse_scale() {
+        let scale = Scale::parse(MEANTONE).unwrap();
+        assert_eq!("1/4-comma meantone scale. Pietro Aaron's temperament (1523)", scale.description);
+        assert_eq!(12, scale.pitches.len());
+        assert!((scale.pitches[3] - 386.3137).abs() < 1e-4);
+        assert_eq!(1200.0, scale.cents(12));
+        assert!((scale.cents(-5) - (696.57843 - 1200.0)).abs() < 1e-9);
+
+        // a 5 note scale of cents and ratios, with comments after the values
+        let scale = Scale::parse("\n5\n200.0 a tone\n3/2 fifth\n700.\n9/5\n2\n").unwrap();
+        assert_eq!("", scale.description);
+        assert_eq!(1200.0, scale.pitches[4]);
+
+        for scl in ["", "scale", "scale\n2\n100.0", "scale\n1\nfifth", "scale\n1\n-3/2", "scale\n0", "scale\n1\n1/0", "scale\n1\n0.0"] {
+            assert!(Scale::parse(scl).is_err(), "{:?}", scl);
+        }
+    }
+
+    #[test]
+    fn test_equal_temperament() {
+        // 12-TET played with the default mapping is the usual note set
+        let scl = format!("12-TET\n12\n{}", (1..=12).map(|step| format!("{}.0\n", step * 100)).collect::<String>());
+        let notes = ScalaTuning::parse(&scl, None).unwrap().notes();
+        for pitch in Pitch::all_notes().iter().filter(|pitch| pitch.midi() <= MAX_KEY) {
+            let note = notes.get(pitch.name()).unwrap();
+            assert_eq!(pitch.midi(), note.midi());
+            assert!((note.frequency() - pitch.frequency()).abs() < 1e-9, "{}", pitch.name());
+        }
+        assert_eq!(MAX_KEY + 1, notes.iter().count());
+    }
+
+    #[test]
+    fn test_keyboard_mapping() {
+        // the white keys play a 7 note scale from middle C at 261.63 Hz, the black ones are left out
+        let kbm = "! white keys\n12\n48\n72\n60\n60\n261.63\n7\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n";
+        let scl = "7 equal\n7\n171.42857\n342.85714\n514.28571\n685.71429\n857.14286\n1028.57143\n2/1\n";
+        let tuning = ScalaTuning::parse(scl, Some(kbm)).unwrap();
+        assert_eq!(Some(261.63), tuning.frequency(60));
+        assert_eq!(None, tuning.frequency(61));
+        assert_eq!(None, tuning.frequency(47));
+        assert!((tuning.frequency(72).unwrap() - 523.26).abs() < 1e-9);
+        assert!((tuning.frequency(59).unwrap() - 261.63 * 2f64.powf(-1.0 / 7.0)).abs() < 1e-3);
+
+        let notes = tuning.notes();
+        assert_eq!(15, notes.iter().count());
+        let guessed = Pitch::guess(&notes, 300.0, None).unwrap();
+        assert_eq!("D4", guessed.name());
+
+        // the reference must be played, and so must some key of the range
+        assert!(ScalaTuning::parse(scl, Some("12\n48\n72\n60\n61\n277.0\n7\n0\nx\n1")).is_err());
+        assert!(ScalaTuning::parse(scl, Some("12\n61\n61\n60\n60\n261.63\n7\n0\nx\n1")).is_err());
+        // a mapping stopping short keeps its size, the keys past its end being left out
+        let short = ScalaTuning::parse(scl, Some("12\n48\n72\n60\n60\n261.63\n7\n0\nx\n1")).unwrap();
+        assert!((short.frequency(62).unwrap() - 261.63 * 2f64.powf(1.0 / 7.0)).abs() < 1e-3);
+        assert_eq!(None, short.frequency(64));
+        assert!((short.frequency(50).unwrap() - 261.63 * 2f64.powf(-6.0 / 7.0)).abs() < 1e-3);
+
+        for kbm in ["12\n48\n72", "0\n72\n48\n60\n69\n440.0\n12", "0\n0\n127\n60\n69\n-440.0\n12", "2\n0\n127\n60\n69\n440.0\n12\n0\nseven",
+            "4294967296\n0\n127\n60\n69\n440.0\n12", "129\n0\n127\n60\n69\n440.0\n12"] {
+            assert!(KeyboardMapping::parse(kbm).is_err(), "{:?}", kbm);
+        }
+        // degrees past the scale cannot be played
+        for kbm in ["1\n0\n127\n60\n60\n261.63\n9223372036854775807\n0", "2\n0\n127\n60\n60\n261.63\n7\n0\n18446744073709551615", "1\n0\n127\n60\n60\n261.63\n8\n0"] {
+            assert!(ScalaTuning::parse(scl, Some(kbm)).is_err(), "{:?}", kbm);
+        }
+    }
+}
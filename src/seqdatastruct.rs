@@ -21,13 +21,10 @@ impl<T> SeqData<T> {
         self._items.insert(String::from(key), self._vec.len() - 1);
     }
 
-    pub fn iter_forward(&self, key: &str) -> Option<Iter<T>> {
-        let iopt = self._items.get(key);
-        if iopt.is_none() {
-            return None
-        }
+    pub fn iter_forward(&self, key: &str) -> Option<Iter<'_, T>> {
+        let index = self._items.get(key)?;
         let mut iter = self._vec.iter();
-        for _ in 0..(*iopt.unwrap()) {
+        for _ in 0..*index {
             match iter.next() {
                 None => return None,
                 Some(_) => continue
@@ -36,13 +33,10 @@ impl<T> SeqData<T> {
         Some(iter)
     }
 
-    pub fn iter_backward(&self, key: &str) -> Option<Rev<Iter<T>>> {
-        let iopt = self._items.get(key);
-        if iopt.is_none() {
-            return None
-        }
+    pub fn iter_backward(&self, key: &str) -> Option<Rev<Iter<'_, T>>> {
+        let index = self._items.get(key)?;
         let mut iter = self._vec.iter().rev();
-        for _ in 0..(self._vec.len() - *iopt.unwrap() -1) {
+        for _ in 0..(self._vec.len() - *index - 1) {
             match iter.next() {
                 None => return None,
                 Some(_) => continue
@@ -51,11 +45,22 @@ impl<T> SeqData<T> {
         Some(iter)
     }
 
+    // every item, in the order they were added
+    pub fn iter(&self) -> Iter<'_, T> {
+        self._vec.iter()
+    }
+
     pub fn get(&self, key: &str) -> Option<&T> {
         self._items.get(&String::from(key)).and_then(|i| self._vec.get(*i))
     }
 }
 
+impl<T> Default for SeqData<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -87,14 +92,25 @@ mod tests {
             seq.add(format!("test{}", i).as_str(), format!("test{} value", i));
         }
 
-        let mut data = seq.iter_forward("test5").unwrap();
+        let data = seq.iter_forward("test5").unwrap();
         let mut str = Vec::new();
-        while let Some(value) = data.next() {
+        for value in data {
             str.push(value.as_str());
         }
         assert_eq!("test5 value;test6 value;test7 value;test8 value", str.join(";"));
     }
 
+    #[test]
+    fn test_iter() {
+        let mut seq: SeqData<String> = SeqData::new();
+        for i in 0..3 {
+            seq.add(format!("test{}", i).as_str(), format!("test{} value", i));
+        }
+
+        let str: Vec<&str> = seq.iter().map(|value| value.as_str()).collect();
+        assert_eq!("test0 value;test1 value;test2 value", str.join(";"));
+    }
+
     
     #[test]
     fn test_iter_forward_not_exists() {
@@ -112,9 +128,9 @@ mod tests {
             seq.add(format!("test{}", i).as_str(), format!("test{} value", i));
         }
 
-        let mut data = seq.iter_backward("test5").unwrap();
+        let data = seq.iter_backward("test5").unwrap();
         let mut str = Vec::new();
-        while let Some(value) = data.next() {
+        for value in data {
             str.push(value.as_str());
         }
         assert_eq!("test5 value;test4 value;test3 value;test2 value;test1 value;test0 value", str.join(";"));
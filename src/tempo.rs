@@ -0,0 +1,174 @@
+use serde::{Serialize, Deserialize};
+
+use crate::detector::parabolic_lag;
+
+// tempi the estimation looks for, in beats per minute
+pub const MIN_BPM: f64 = 40.0;
+pub const MAX_BPM: f64 = 240.0;
+// tempo listeners lean towards, the periodicity is weighted by a log-gaussian around it
+const PREFERRED_BPM: f64 = 120.0;
+// standard deviation of that weighting, in octaves of tempo
+const TEMPO_SPREAD_OCTAVES: f64 = 1.0;
+// how strongly the beat tracker keeps to the tempo, against following the onsets (Ellis, 2007)
+const TIGHTNESS: f64 = 100.0;
+
+// tempo of a recording and the time of every beat, in seconds
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tempo {
+    pub bpm: f64,
+    pub beats: Vec<f64>,
+}
+
+fn normalized(envelope: &[f64]) -> Vec<f64> {
+    let mean = envelope.iter().sum::<f64>() / envelope.len() as f64;
+    let deviation = (envelope.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / envelope.len() as f64).sqrt();
+    if deviation == 0.0 {
+        return vec![0.0; envelope.len()];
+    }
+    envelope.iter().map(|x| x / deviation).collect()
+}
+
+// tempo of an onset envelope with one value per hop, from the lag its autocorrelation peaks
+// at once weighted towards PREFERRED_BPM. none when nothing repeats
+pub fn estimate_tempo(envelope: &[f64], hop_length: f64) -> Option<f64> {
+    let mean = envelope.iter().sum::<f64>() / envelope.len().max(1) as f64;
+    let centered: Vec<f64> = envelope.iter().map(|x| x - mean).collect();
+    let min_lag = ((60.0 / MAX_BPM / hop_length).floor() as usize).max(1);
+    let max_lag = ((60.0 / MIN_BPM / hop_length).ceil() as usize).min(envelope.len().saturating_sub(1));
+    if min_lag + 1 >= max_lag {
+        return None;
+    }
+
+    let preferred_lag = 60.0 / PREFERRED_BPM / hop_length;
+    let weighted: Vec<f64> = (0..=max_lag + 1).map(|lag| {
+        if lag == 0 || lag >= envelope.len() {
+            return 0.0;
+        }
+        let autocorrelation: f64 = (lag..envelope.len()).map(|t| centered[t] * centered[t - lag]).sum::<f64>() / (envelope.len() - lag) as f64;
+        let octaves = (lag as f64 / preferred_lag).log2() / TEMPO_SPREAD_OCTAVES;
+        autocorrelation * (-0.5 * octaves * octaves).exp()
+    }).collect();
+    let best = (min_lag..=max_lag).max_by(|a, b| weighted[*a].total_cmp(&weighted[*b]))?;
+    if weighted[best] <= 0.0 {
+        return None;
+    }
+    Some(60.0 / (parabolic_lag(&weighted, best) * hop_length))
+}
+
+// frames the beats fall on, chosen by dynamic programming so they land on strong onsets
+// while keeping about the given tempo apart. the silences before the first onset and after
+// the last one have no beats
+pub fn track_beats(envelope: &[f64], hop_length: f64, bpm: f64) -> Vec<usize> {
+    if envelope.is_empty() {
+        return vec![];
+    }
+    let period = 60.0 / bpm / hop_length;
+    let envelope = normalized(envelope);
+    let mut scores = envelope.clone();
+    let mut previous: Vec<Option<usize>> = vec![None; envelope.len()];
+    for t in 0..envelope.len() {
+        let earliest = t.saturating_sub((2.0 * period).round() as usize);
+        let latest = t.saturating_sub((period / 2.0).round() as usize);
+        let best = (earliest..latest)
+            .map(|from| {
+                let deviation = ((t - from) as f64 / period).ln();
+                (from, scores[from] - TIGHTNESS * deviation * deviation)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((from, score)) = best {
+            scores[t] += score;
+            previous[t] = Some(from);
+        }
+    }
+
+    // the last beat is the best scored of the last period
+    let tail = envelope.len().saturating_sub(period.round() as usize);
+    let mut beat = (tail..envelope.len()).max_by(|a, b| scores[*a].total_cmp(&scores[*b]));
+    let mut beats = vec![];
+    while let Some(k) = beat {
+        beats.push(k);
+        beat = previous[k];
+    }
+    beats.reverse();
+    let first = envelope.iter().position(|x| *x > 0.0).unwrap_or(envelope.len());
+    let last = envelope.iter().rposition(|x| *x > 0.0).unwrap_or(0);
+    beats.retain(|k| (first..=last).contains(k));
+    beats
+}
+
+// position of a time on the beat grid, in beats from the first beat. times before the first
+// beat or after the last one are placed with the period of the nearest beats
+pub fn beat_position(beats: &[f64], time: f64) -> Option<f64> {
+    if beats.len() < 2 {
+        return None;
+    }
+    let i = beats.partition_point(|beat| *beat <= time).clamp(1, beats.len() - 1) - 1;
+    Some(i as f64 + (time - beats[i]) / (beats[i + 1] - beats[i]))
+}
+
+// time of a position on the beat grid, the reverse of beat_position
+pub fn beat_time(beats: &[f64], position: f64) -> Option<f64> {
+    if beats.len() < 2 {
+        return None;
+    }
+    let i = (position.floor().max(0.0) as usize).min(beats.len() - 2);
+    Some(beats[i] + (position - i as f64) * (beats[i + 1] - beats[i]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an attack every period frames from the first one, plus a weaker one half way
+    fn envelope(frames: usize, first: usize, period: usize) -> Vec<f64> {
+        (0..frames).map(|k| match k.checked_sub(first) {
+            Some(d) if d % period == 0 => 1.0,
+            Some(d) if d % period == period / 2 => 0.3,
+            _ => 0.0,
+        }).collect()
+    }
+
+    #[test]
+    fn test_estimate_tempo() {
+        // 0.5 s apart with a hop of 10 ms
+        let bpm = estimate_tempo(&envelope(600, 13, 50), 0.01).unwrap();
+        assert!((bpm - 120.0).abs() < 1.0, "{}", bpm);
+        let bpm = estimate_tempo(&envelope(600, 0, 75), 0.01).unwrap();
+        assert!((bpm - 80.0).abs() < 1.0, "{}", bpm);
+        assert_eq!(None, estimate_tempo(&[0.0; 600], 0.01));
+        assert_eq!(None, estimate_tempo(&[1.0; 10], 0.01));
+    }
+
+    #[test]
+    fn test_track_beats() {
+        let beats = track_beats(&envelope(600, 13, 50), 0.01, 120.0);
+        let expected: Vec<usize> = (13..600).step_by(50).collect();
+        assert_eq!(expected, beats);
+
+        // a slightly early attack still gets the beat
+        let mut shifted = envelope(600, 13, 50);
+        shifted.swap(213, 210);
+        assert!(track_beats(&shifted, 0.01, 120.0).contains(&210));
+
+        // and leading silence has none
+        let beats = track_beats(&envelope(600, 213, 50), 0.01, 120.0);
+        assert_eq!(Some(&213), beats.first());
+        assert!(track_beats(&[0.0; 600], 0.01, 120.0).is_empty());
+    }
+
+    #[test]
+    fn test_beat_position() {
+        // slowing down on the last beat
+        let beats = [1.0, 1.5, 2.0, 3.0];
+        assert_eq!(Some(0.0), beat_position(&beats, 1.0));
+        assert_eq!(Some(1.5), beat_position(&beats, 1.75));
+        assert_eq!(Some(2.5), beat_position(&beats, 2.5));
+        assert_eq!(Some(-1.0), beat_position(&beats, 0.5));
+        assert_eq!(Some(4.0), beat_position(&beats, 4.0));
+        assert_eq!(None, beat_position(&beats[..1], 1.0));
+
+        for time in [0.5, 1.0, 1.75, 2.5, 4.0] {
+            assert_eq!(Some(time), beat_time(&beats, beat_position(&beats, time).unwrap()));
+        }
+    }
+}
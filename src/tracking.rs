@@ -0,0 +1,150 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{detector::PitchEstimate, notes::{Pitch, cents_between}};
+
+// spread of the frequency of a held note around the note, in cents
+const PITCH_SPREAD_CENTS: f64 = 40.0;
+// likelihood of a frame disagreeing with the note actually played, so a single outlier
+// costs a fixed penalty instead of forcing a change of note
+const OUTLIER_PROBABILITY: f64 = 1e-3;
+
+// how frame estimates are turned into notes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoteTracking {
+    // every frame keeps the note nearest to its own frequency
+    None,
+    // hidden Markov model over the notes and silence, decoded with Viterbi
+    #[default]
+    Hmm,
+}
+
+// log likelihood of a frame estimate for every state, silence first then the notes
+fn emissions(notes: &[Pitch], estimate: &Option<PitchEstimate>) -> Vec<f64> {
+    let outlier = OUTLIER_PROBABILITY.ln();
+    match estimate {
+        Some(estimate) if estimate.frequency > 0.0 => {
+            let confidence = estimate.confidence.clamp(0.0, 1.0);
+            std::iter::once(outlier)
+                .chain(notes.iter().map(|note| {
+                    let distance = cents_between(estimate.frequency, note.frequency()) / PITCH_SPREAD_CENTS;
+                    (confidence * (-0.5 * distance * distance).exp() + OUTLIER_PROBABILITY).ln()
+                }))
+                .collect()
+        },
+        _ => std::iter::once(0.0).chain(notes.iter().map(|_| outlier)).collect(),
+    }
+}
+
+// most likely note, or silence, of every frame. leaving a note costs as much as half of
+// min_frames outlier frames, so the new note needs about min_frames frames of evidence
+pub fn track_notes(notes: &[Pitch], estimates: &[Option<PitchEstimate>], min_frames: usize) -> Vec<Pitch> {
+    if estimates.is_empty() {
+        return vec![];
+    }
+    let switch_cost = (min_frames as f64 - 0.5) / 2.0 * -OUTLIER_PROBABILITY.ln();
+    let states = notes.len() + 1;
+
+    let mut scores = emissions(notes, &estimates[0]);
+    // for every frame after the first, the state each state was reached from
+    let mut origins: Vec<Vec<usize>> = Vec::with_capacity(estimates.len());
+    for estimate in &estimates[1..] {
+        let best = (0..states).max_by(|a, b| scores[*a].total_cmp(&scores[*b])).unwrap();
+        let emission = emissions(notes, estimate);
+        let mut origin = vec![0; states];
+        scores = (0..states).map(|s| {
+            let (from, score) = if scores[s] >= scores[best] - switch_cost { (s, scores[s]) } else { (best, scores[best] - switch_cost) };
+            origin[s] = from;
+            score + emission[s]
+        }).collect();
+        origins.push(origin);
+    }
+
+    let mut state = (0..states).max_by(|a, b| scores[*a].total_cmp(&scores[*b])).unwrap();
+    let mut path = vec![state];
+    for origin in origins.iter().rev() {
+        state = origin[state];
+        path.push(state);
+    }
+    path.reverse();
+
+    path.into_iter().zip(estimates).map(|(state, estimate)| {
+        match (state, estimate) {
+            (0, _) => Pitch::silence(),
+            (s, Some(estimate)) if estimate.frequency > 0.0 => {
+                let note = &notes[s - 1];
+                note.detuned(cents_between(estimate.frequency, note.frequency()))
+            },
+            (s, _) => notes[s - 1].clone(),
+        }
+    }).collect()
+}
+
+impl FromStr for NoteTracking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(NoteTracking::None),
+            "hmm" => Ok(NoteTracking::Hmm),
+            _ => Err(format!("unknown note tracking '{}'", s)),
+        }
+    }
+}
+
+impl Display for NoteTracking {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoteTracking::None => write!(f, "none"),
+            NoteTracking::Hmm => write!(f, "hmm"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(frequency: f64) -> Option<PitchEstimate> {
+        Some(PitchEstimate { frequency, confidence: 0.9 })
+    }
+
+    fn names(pitches: &[Pitch]) -> Vec<&str> {
+        pitches.iter().map(|pitch| pitch.name()).collect()
+    }
+
+    #[test]
+    fn test_track_outliers() {
+        let notes: Vec<Pitch> = Pitch::all_notes().iter().cloned().collect();
+        // a held A4 with an octave error, a misdetected frame and a dropout
+        let mut estimates = vec![estimate(440.0); 30];
+        estimates[8] = estimate(880.0);
+        estimates[15] = estimate(466.0);
+        estimates[16] = estimate(470.0);
+        estimates[22] = None;
+        let tracked = track_notes(&notes, &estimates, 5);
+        assert_eq!(vec!["A4"; 30], names(&tracked));
+        assert_eq!(0.0, tracked[0].cents());
+        assert!((tracked[15].cents() - 99.0).abs() < 1.0);
+
+        // a real change of note lasting long enough is kept
+        let estimates: Vec<Option<PitchEstimate>> = [estimate(440.0); 10].into_iter()
+            .chain([estimate(494.0); 6])
+            .chain([None; 10])
+            .collect();
+        let tracked = track_notes(&notes, &estimates, 5);
+        let expected: Vec<&str> = ["A4"; 10].into_iter().chain(["B4"; 6]).chain(["S"; 10]).collect();
+        assert_eq!(expected, names(&tracked));
+
+        // and with long frames a single one is enough
+        let estimates = [estimate(440.0), estimate(494.0), estimate(440.0)];
+        assert_eq!(vec!["A4", "B4", "A4"], names(&track_notes(&notes, &estimates, 1)));
+    }
+
+    #[test]
+    fn test_parse_note_tracking() {
+        for tracking in [NoteTracking::None, NoteTracking::Hmm] {
+            assert_eq!(Ok(tracking), tracking.to_string().parse());
+        }
+        assert!("median".parse::<NoteTracking>().is_err());
+    }
+}
@@ -1,119 +1,265 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, str::FromStr, sync::OnceLock};
 
+use crate::notes::{A4, CENTS_PER_OCTAVE, CENTS_PER_SEMITONE, NOTE_NAMES, SEMITONES_PER_OCTAVE};
+
+// midi number of A4, the note the reference frequency is given for
+pub const A4_MIDI: usize = 69;
+// midi numbers of A0 and B9, the lowest and highest notes of the note chart
+const CHART_LOWEST: usize = 21;
+const CHART_HIGHEST: usize = 131;
+// lowest and highest reference accepted for A4, from the low french baroque pitch to
+// the high venetian one with some room around them, in Hz
+pub const MIN_REFERENCE: f64 = 370.0;
+pub const MAX_REFERENCE: f64 = 500.0;
+// pitch class of A, which keeps the reference frequency whatever the temperament
+const A_PITCH_CLASS: usize = 9;
+
+// fifths above C of every pitch class, the circle being broken between G# and Eb
+const FIFTHS_FROM_C: [i32; SEMITONES_PER_OCTAVE] = [0, 7, 2, -3, 4, -1, 6, 1, 8, 3, -2, 5];
+// the well temperaments, in cents above the tonic
+const WERCKMEISTER_III: [f64; SEMITONES_PER_OCTAVE] = [
+    0.0, 90.225, 192.18, 294.135, 390.225, 498.045, 588.27, 696.09, 792.18, 888.27, 996.09, 1092.18
+];
+const VALLOTTI: [f64; SEMITONES_PER_OCTAVE] = [
+    0.0, 94.135, 196.09, 298.045, 392.18, 501.955, 592.18, 698.045, 796.09, 894.135, 1000.0, 1090.225
+];
+const KIRNBERGER_III: [f64; SEMITONES_PER_OCTAVE] = [
+    0.0, 90.225, 193.157, 294.135, 386.314, 498.045, 590.224, 696.578, 792.18, 889.735, 996.09, 1088.269
+];
+// 5-limit ratios above the tonic
+const JUST_RATIOS: [(u32, u32); SEMITONES_PER_OCTAVE] = [
+    (1, 1), (16, 15), (9, 8), (6, 5), (5, 4), (4, 3), (45, 32), (3, 2), (8, 5), (5, 3), (9, 5), (15, 8)
+];
+
+fn ratio_cents(numerator: u32, denominator: u32) -> f64 {
+    CENTS_PER_OCTAVE * (numerator as f64 / denominator as f64).log2()
+}
+
+// every degree of a chain of fifths of the given size, from Eb to G# above the tonic
+fn chain_of_fifths(fifth: f64) -> [f64; SEMITONES_PER_OCTAVE] {
+    FIFTHS_FROM_C.map(|fifths| (fifths as f64 * fifth).rem_euclid(CENTS_PER_OCTAVE))
+}
+
+// pitch class of a note name without octave, such as C, F# or Bb
+pub fn parse_pitch_class(name: &str) -> Option<usize> {
+    let letter = NOTE_NAMES.iter().position(|note| note.len() == 1 && name.starts_with(note))?;
+    let accidental: i32 = name[1..].chars().map(|c| match c {
+        '#' => Some(1),
+        'b' => Some(-1),
+        _ => None,
+    }).sum::<Option<i32>>()?;
+    Some((letter as i32 + accidental).rem_euclid(SEMITONES_PER_OCTAVE as i32) as usize)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TemperamentKind {
+    #[default]
+    Equal,
+    // pure fifths, the wolf fifth between G# and Eb
+    Pythagorean,
+    // fifths narrowed by a quarter of the syntonic comma, so the major thirds are pure
+    QuarterCommaMeantone,
+    WerckmeisterIII,
+    Vallotti,
+    KirnbergerIII,
+    // 5-limit just intonation
+    Just,
+}
+
+// how the twelve notes of an octave are tuned, laid out from a tonic
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Temperament {
+    pub kind: TemperamentKind,
+    // pitch class the temperament is laid out from, C by default
+    pub tonic: usize,
+}
+
+impl Temperament {
+    // cents above the tonic of every degree of the chromatic scale
+    pub fn degrees(&self) -> [f64; SEMITONES_PER_OCTAVE] {
+        match self.kind {
+            TemperamentKind::Equal => std::array::from_fn(|degree| degree as f64 * CENTS_PER_SEMITONE),
+            TemperamentKind::Pythagorean => chain_of_fifths(ratio_cents(3, 2)),
+            TemperamentKind::QuarterCommaMeantone => chain_of_fifths(ratio_cents(3, 2) - ratio_cents(81, 80) / 4.0),
+            TemperamentKind::WerckmeisterIII => WERCKMEISTER_III,
+            TemperamentKind::Vallotti => VALLOTTI,
+            TemperamentKind::KirnbergerIII => KIRNBERGER_III,
+            TemperamentKind::Just => JUST_RATIOS.map(|(numerator, denominator)| ratio_cents(numerator, denominator)),
+        }
+    }
+
+    // distance of a pitch class from its equal tempered pitch, in cents
+    fn deviation(&self, pitch_class: usize) -> f64 {
+        let degree = (pitch_class + SEMITONES_PER_OCTAVE - self.tonic) % SEMITONES_PER_OCTAVE;
+        self.degrees()[degree] - degree as f64 * CENTS_PER_SEMITONE
+    }
+}
+
+impl FromStr for Temperament {
+    type Err = String;
+
+    // the temperament takes an optional tonic, as in just:D
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, tonic) = s.split_once(':').unwrap_or((s, "C"));
+        let kind = match kind {
+            "equal" => TemperamentKind::Equal,
+            "pythagorean" => TemperamentKind::Pythagorean,
+            "meantone" => TemperamentKind::QuarterCommaMeantone,
+            "werckmeister3" => TemperamentKind::WerckmeisterIII,
+            "vallotti" => TemperamentKind::Vallotti,
+            "kirnberger3" => TemperamentKind::KirnbergerIII,
+            "just" => TemperamentKind::Just,
+            _ => return Err(format!("unknown temperament '{}'", s)),
+        };
+        let tonic = parse_pitch_class(tonic).ok_or_else(|| format!("invalid tonic '{}'", tonic))?;
+        Ok(Temperament { kind, tonic })
+    }
+}
+
+impl Display for Temperament {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            TemperamentKind::Equal => "equal",
+            TemperamentKind::Pythagorean => "pythagorean",
+            TemperamentKind::QuarterCommaMeantone => "meantone",
+            TemperamentKind::WerckmeisterIII => "werckmeister3",
+            TemperamentKind::Vallotti => "vallotti",
+            TemperamentKind::KirnbergerIII => "kirnberger3",
+            TemperamentKind::Just => "just",
+        };
+        write!(f, "{}:{}", kind, NOTE_NAMES[self.tonic])
+    }
+}
+
+// a temperament, with A4 at the reference frequency
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+    pub temperament: Temperament,
+    pub reference: f64,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            temperament: Temperament::default(),
+            reference: A4
+        }
+    }
+}
+
+impl Tuning {
+    pub fn equal(reference: f64) -> Tuning {
+        Tuning {
+            temperament: Temperament::default(),
+            reference
+        }
+    }
+
+    pub fn frequency(&self, midi: usize) -> f64 {
+        let cents = self.temperament.deviation(midi % SEMITONES_PER_OCTAVE) - self.temperament.deviation(A_PITCH_CLASS);
+        equal_temperament_frequency(midi, self.reference) * 2f64.powf(cents / CENTS_PER_OCTAVE)
+    }
+
+    // a map of all notes from A0 to B9 as key and their frequencies as value
+    pub fn table(&self) -> HashMap<String, f64> {
+        let mut tuning = HashMap::new();
+        for midi in CHART_LOWEST..=CHART_HIGHEST {
+            tuning.insert(midi_name(midi), self.frequency(midi));
+        }
+        tuning
+    }
+}
+
+// name of a midi note, such as C-1 for 0 and A4 for 69
+pub fn midi_name(midi: usize) -> String {
+    format!("{}{}", NOTE_NAMES[midi % SEMITONES_PER_OCTAVE], (midi / SEMITONES_PER_OCTAVE) as i64 - 1)
+}
+
+// frequency of a midi note in twelve-tone equal temperament with A4 at the reference
+pub fn equal_temperament_frequency(midi: usize, reference: f64) -> f64 {
+    reference * 2f64.powf((midi as f64 - A4_MIDI as f64) / SEMITONES_PER_OCTAVE as f64)
+}
+
+pub fn equal_temperament(reference: f64) -> HashMap<String, f64> {
+    Tuning::equal(reference).table()
+}
+
+// names of the notes from A0 to B9, made once so the chart can be keyed by them
+fn chart_names() -> &'static [String] {
+    static NAMES: OnceLock<Vec<String>> = OnceLock::new();
+    NAMES.get_or_init(|| (CHART_LOWEST..=CHART_HIGHEST).map(midi_name).collect())
+}
+
+// the printed note chart, equal temperament with A4 at 440 Hz
 pub fn std_tuning() -> HashMap<&'static str, f64> {
-    // a map of all notes from A0 to A9 as key and their frequencies as value
-    let mut tuning = HashMap::new();
-    tuning.insert("A0", 27.5);
-    tuning.insert("A#0", 29.135);
-    tuning.insert("B0", 30.868);
-    tuning.insert("C1", 32.703);
-    tuning.insert("C#1", 34.648);
-    tuning.insert("D1", 36.708);
-    tuning.insert("D#1", 38.891);
-    tuning.insert("E1", 41.203);
-    tuning.insert("F1", 43.654);
-    tuning.insert("F#1", 46.249);
-    tuning.insert("G1", 48.999);
-    tuning.insert("G#1", 51.913);
-    tuning.insert("A1", 55.0);
-    tuning.insert("A#1", 58.27);
-    tuning.insert("B1", 61.735);
-    tuning.insert("C2", 65.406);
-    tuning.insert("C#2", 69.296);
-    tuning.insert("D2", 73.416);
-    tuning.insert("D#2", 77.782);
-    tuning.insert("E2", 82.407);
-    tuning.insert("F2", 87.307);
-    tuning.insert("F#2", 92.499);
-    tuning.insert("G2", 97.999);
-    tuning.insert("G#2", 103.826);
-    tuning.insert("A2", 110.0);
-    tuning.insert("A#2", 116.541);
-    tuning.insert("B2", 123.471);
-    tuning.insert("C3", 130.813);
-    tuning.insert("C#3", 138.591);
-    tuning.insert("D3", 146.832);
-    tuning.insert("D#3", 155.563);
-    tuning.insert("E3", 164.814);
-    tuning.insert("F3", 174.614);
-    tuning.insert("F#3", 184.997);
-    tuning.insert("G3", 195.998);
-    tuning.insert("G#3", 207.652);
-    tuning.insert("A3", 220.0);
-    tuning.insert("A#3", 233.082);
-    tuning.insert("B3", 246.942);
-    tuning.insert("C4", 261.626);
-    tuning.insert("C#4", 277.183);
-    tuning.insert("D4", 293.665);
-    tuning.insert("D#4", 311.127);
-    tuning.insert("E4", 329.628);
-    tuning.insert("F4", 349.228);
-    tuning.insert("F#4", 369.994);
-    tuning.insert("G4", 391.995);
-    tuning.insert("G#4", 415.305);
-    tuning.insert("A4", 440.0);
-    tuning.insert("A#4", 466.164);
-    tuning.insert("B4", 493.883);
-    tuning.insert("C5", 523.251);
-    tuning.insert("C#5", 554.365);
-    tuning.insert("D5", 587.33);
-    tuning.insert("D#5", 622.254);
-    tuning.insert("E5", 659.255);
-    tuning.insert("F5", 698.456);
-    tuning.insert("F#5", 739.989);
-    tuning.insert("G5", 783.991);
-    tuning.insert("G#5", 830.609);
-    tuning.insert("A5", 880.0);
-    tuning.insert("A#5", 932.328);
-    tuning.insert("B5", 987.767);
-    tuning.insert("C6", 1046.502);
-    tuning.insert("C#6", 1108.731);
-    tuning.insert("D6", 1174.659);
-    tuning.insert("D#6", 1244.508);
-    tuning.insert("E6", 1318.51);
-    tuning.insert("F6", 1396.913);
-    tuning.insert("F#6", 1479.978);
-    tuning.insert("G6", 1567.982);
-    tuning.insert("G#6", 1661.219);
-    tuning.insert("A6", 1760.0);
-    tuning.insert("A#6", 1864.655);
-    tuning.insert("B6", 1975.533);
-    tuning.insert("C7", 2093.005);
-    tuning.insert("C#7", 2217.461);
-    tuning.insert("D7", 2349.318);
-    tuning.insert("D#7", 2489.016);
-    tuning.insert("E7", 2637.02);
-    tuning.insert("F7", 2793.826);
-    tuning.insert("F#7", 2959.955);
-    tuning.insert("G7", 3135.963);
-    tuning.insert("G#7", 3322.438);
-    tuning.insert("A7", 3520.0);
-    tuning.insert("A#7", 3729.31);
-    tuning.insert("B7", 3951.066);
-    tuning.insert("C8", 4186.009);
-    tuning.insert("C#8", 4434.922);
-    tuning.insert("D8", 4698.636);
-    tuning.insert("D#8", 4978.032);
-    tuning.insert("E8", 5274.041);
-    tuning.insert("F8", 5587.652);
-    tuning.insert("F#8", 5919.91);
-    tuning.insert("G8", 6271.927);
-    tuning.insert("G#8", 6644.875);
-    tuning.insert("A8", 7040.0);
-    tuning.insert("A#8", 7458.62);
-    tuning.insert("B8", 7902.132);
-    tuning.insert("C9", 8372.018);
-    tuning.insert("C#9", 8869.844);
-    tuning.insert("D9", 9397.272);
-    tuning.insert("D#9", 9956.064);
-    tuning.insert("E9", 10548.082);
-    tuning.insert("F9", 11175.304);
-    tuning.insert("F#9", 11839.82);
-    tuning.insert("G9", 12543.854);
-    tuning.insert("G#9", 13289.75);
-    tuning.insert("A9", 14080.0);
-    tuning.insert("A#9", 14917.24);
-    tuning.insert("B9", 15804.264);
-    
-    tuning
-}
\ No newline at end of file
+    let tuning = Tuning::equal(A4);
+    chart_names().iter().zip(CHART_LOWEST..).map(|(name, midi)| (name.as_str(), tuning.frequency(midi))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_std_tuning() {
+        // the values of the printed note chart, given to the millihertz
+        let tuning = std_tuning();
+        assert_eq!(111, tuning.len());
+        for (name, frequency) in [("A0", 27.5), ("C1", 32.703), ("C4", 261.626), ("A4", 440.0), ("G#8", 6644.875), ("B9", 15804.264)] {
+            assert!((tuning[name] - frequency).abs() < 0.01, "{} {}", name, tuning[name]);
+        }
+    }
+
+    #[test]
+    fn test_equal_temperament() {
+        for reference in [415.0, 432.0, 442.0, 443.0] {
+            let tuning = equal_temperament(reference);
+            assert_eq!(reference, tuning["A4"]);
+            assert_eq!(reference / 2.0, tuning["A3"]);
+            assert!((tuning["E5"] / tuning["A4"] - 2f64.powf(7.0 / 12.0)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_temperaments() {
+        let tuning = |temperament: &str| Tuning { temperament: temperament.parse().unwrap(), reference: A4 }.table();
+        let interval = |table: &HashMap<String, f64>, low: &str, high: &str| table[high] / table[low];
+
+        // pure fifths, and a pure major third in meantone
+        let pythagorean = tuning("pythagorean");
+        assert!((interval(&pythagorean, "C4", "G4") - 1.5).abs() < 1e-12);
+        assert!((interval(&pythagorean, "D4", "A4") - 1.5).abs() < 1e-12);
+        let meantone = tuning("meantone");
+        assert!((interval(&meantone, "C4", "E4") - 1.25).abs() < 1e-12);
+
+        // just intonation from its tonic, the A keeping the reference
+        let just = tuning("just:D");
+        assert_eq!(440.0, just["A4"]);
+        assert!((interval(&just, "D4", "F#4") - 1.25).abs() < 1e-12);
+        assert!((interval(&just, "D4", "G4") - 4.0 / 3.0).abs() < 1e-12);
+        assert!((interval(&just, "D4", "D5") - 2.0).abs() < 1e-12);
+        // the classic C of 264 Hz
+        assert!((tuning("just")["C4"] - 264.0).abs() < 1e-9);
+
+        for temperament in ["werckmeister3", "vallotti", "kirnberger3"] {
+            let table = tuning(temperament);
+            assert_eq!(440.0, table["A4"]);
+            // well temperaments stay within a quarter of a semitone of equal temperament
+            for (name, frequency) in std_tuning() {
+                assert!(crate::notes::cents_between(table[name], frequency).abs() < 25.0, "{} {}", temperament, name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_temperament() {
+        for temperament in ["equal:C", "meantone:D#", "just:F#", "werckmeister3:C"] {
+            assert_eq!(temperament, temperament.parse::<Temperament>().unwrap().to_string());
+        }
+        assert_eq!(Ok(Temperament { kind: TemperamentKind::Just, tonic: 10 }), "just:Bb".parse());
+        assert_eq!(Ok(Temperament { kind: TemperamentKind::Pythagorean, tonic: 0 }), "pythagorean".parse());
+        for temperament in ["mean", "just:H", "just:", "pythagorean:C4"] {
+            assert!(temperament.parse::<Temperament>().is_err(), "{}", temperament);
+        }
+    }
+}
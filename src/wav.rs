@@ -1,40 +1,232 @@
+use std::fmt::Display;
 use std::{fs::File, f64::consts::PI};
 use std::io::Write;
 
-use byteorder::{WriteBytesExt, LittleEndian};
+use byteorder::{ByteOrder, WriteBytesExt, LittleEndian};
 
 use crate::notes::PhiNote;
 
 pub const SAMPLE_RATE: f64 = 44100.0;
+pub const WAVE_FORMAT_PCM: u16 = 0x0001;
+pub const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+pub const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+// sample rates accepted from a header, every recording being resampled to SAMPLE_RATE
+pub const MIN_SAMPLE_RATE: u32 = 8000;
+pub const MAX_SAMPLE_RATE: u32 = 192000;
+// the KSDATAFORMAT_SUBTYPE_* GUIDs only differ by the format tag stored in their first two bytes
+const SUBFORMAT_GUID_SUFFIX: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71
+];
 
-pub fn write_to_file(filename: &str, data: &Vec<u8>) -> std::io::Result<()> {
+pub fn write_to_file(filename: &str, data: &[u8]) -> std::io::Result<()> {
     let mut file = File::create(filename)?;
     write_wav_header(&mut file, data, 16, 1, SAMPLE_RATE as u32);
     file.flush()?;
     Ok(())
 }
 
-pub fn write_wav_header(output: &mut File, data: &Vec<u8>, bits_per_sample: u16, channels: u16, sample_rate: u32) {
+pub fn write_wav_header(output: &mut impl Write, data: &[u8], bits_per_sample: u16, channels: u16, sample_rate: u32) {
     let data_len = data.len() as u32;
     let byte_rate = sample_rate * (bits_per_sample as u32 / 8) * channels as u32;
     let block_align = (bits_per_sample / 8) * channels;
     
-    std::io::Write::write_all(output,b"RIFF").unwrap();
-    WriteBytesExt::write_u32::<LittleEndian>(output, 36 + data_len).unwrap();
-    std::io::Write::write_all(output,b"WAVE").unwrap();
-    std::io::Write::write_all(output,b"fmt ").unwrap();
-    WriteBytesExt::write_u32::<LittleEndian>(output, 16).unwrap();
-    WriteBytesExt::write_u16::<LittleEndian>(output, 1).unwrap();
-    WriteBytesExt::write_u16::<LittleEndian>(output, channels).unwrap();
-    WriteBytesExt::write_u32::<LittleEndian>(output, sample_rate).unwrap();
-    WriteBytesExt::write_u32::<LittleEndian>(output, byte_rate).unwrap();
-    WriteBytesExt::write_u16::<LittleEndian>(output, block_align).unwrap();
-    WriteBytesExt::write_u16::<LittleEndian>(output, bits_per_sample).unwrap();
-    std::io::Write::write_all(output,b"data").unwrap();
-    WriteBytesExt::write_u32::<LittleEndian>(output, data_len).unwrap();
-    std::io::Write::write_all(output,data).unwrap();
+    output.write_all(b"RIFF").unwrap();
+    output.write_u32::<LittleEndian>(36 + data_len).unwrap();
+    output.write_all(b"WAVE").unwrap();
+    output.write_all(b"fmt ").unwrap();
+    output.write_u32::<LittleEndian>(16).unwrap();
+    output.write_u16::<LittleEndian>(WAVE_FORMAT_PCM).unwrap();
+    output.write_u16::<LittleEndian>(channels).unwrap();
+    output.write_u32::<LittleEndian>(sample_rate).unwrap();
+    output.write_u32::<LittleEndian>(byte_rate).unwrap();
+    output.write_u16::<LittleEndian>(block_align).unwrap();
+    output.write_u16::<LittleEndian>(bits_per_sample).unwrap();
+    output.write_all(b"data").unwrap();
+    output.write_u32::<LittleEndian>(data_len).unwrap();
+    output.write_all(data).unwrap();
 }
 
+// wrap raw PCM data into an in-memory WAV file
+pub fn to_wav_file(data: &[u8], bits_per_sample: u16, channels: u16, sample_rate: u32) -> Vec<u8> {
+    let mut file = Vec::with_capacity(data.len() + 44);
+    write_wav_header(&mut file, data, bits_per_sample, channels, sample_rate);
+    file
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    pub fn bytes(&self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::I16 => 2,
+            SampleFormat::I24 => 3,
+            SampleFormat::I32 | SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+        }
+    }
+
+    // decode a single little-endian sample into the [-1.0, 1.0] range. a NaN or infinite float
+    // sample is taken as silence
+    pub fn decode(&self, bytes: &[u8]) -> f64 {
+        let sample = match self {
+            SampleFormat::U8 => (bytes[0] as f64 - 128.0) / 128.0,
+            SampleFormat::I16 => LittleEndian::read_i16(bytes) as f64 / 32768.0,
+            SampleFormat::I24 => LittleEndian::read_i24(bytes) as f64 / 8388608.0,
+            SampleFormat::I32 => LittleEndian::read_i32(bytes) as f64 / 2147483648.0,
+            SampleFormat::F32 => LittleEndian::read_f32(bytes) as f64,
+            SampleFormat::F64 => LittleEndian::read_f64(bytes),
+        };
+        if sample.is_finite() { sample } else { 0.0 }
+    }
+}
+
+// the format tag of WAVE_FORMAT_EXTENSIBLE files is resolved to the one of their sub format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavFormat {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+}
+
+impl WavFormat {
+    pub fn pcm(channels: u16, sample_rate: u32, bits_per_sample: u16) -> WavFormat {
+        WavFormat {
+            format_tag: WAVE_FORMAT_PCM,
+            channels,
+            sample_rate,
+            bits_per_sample
+        }
+    }
+
+    pub fn sample_format(&self) -> Result<SampleFormat, WavError> {
+        match (self.format_tag, self.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => Ok(SampleFormat::U8),
+            (WAVE_FORMAT_PCM, 16) => Ok(SampleFormat::I16),
+            (WAVE_FORMAT_PCM, 24) => Ok(SampleFormat::I24),
+            (WAVE_FORMAT_PCM, 32) => Ok(SampleFormat::I32),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Ok(SampleFormat::F32),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Ok(SampleFormat::F64),
+            (WAVE_FORMAT_PCM, bits) | (WAVE_FORMAT_IEEE_FLOAT, bits) => {
+                Err(WavError::Unsupported(format!("{} bits per sample", bits)))
+            },
+            (tag, _) => Err(WavError::Unsupported(format!("format tag 0x{:04x}", tag))),
+        }
+    }
+}
+
+pub struct Wav<'a> {
+    pub format: WavFormat,
+    pub data: &'a [u8],
+}
+
+impl Wav<'_> {
+    // decode the interleaved samples of every channel, trailing incomplete samples are dropped
+    pub fn samples(&self) -> Result<Vec<f64>, WavError> {
+        let sample_format = self.format.sample_format()?;
+        Ok(self.data
+            .chunks_exact(sample_format.bytes())
+            .map(|sample| sample_format.decode(sample))
+            .collect())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WavError {
+    NotRiff,
+    Truncated,
+    MissingFormat,
+    MissingData,
+    Unsupported(String),
+}
+
+impl Display for WavError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WavError::NotRiff => write!(f, "not a RIFF/WAVE file"),
+            WavError::Truncated => write!(f, "truncated WAV header"),
+            WavError::MissingFormat => write!(f, "missing fmt chunk"),
+            WavError::MissingData => write!(f, "missing data chunk"),
+            WavError::Unsupported(reason) => write!(f, "unsupported WAV encoding: {}", reason),
+        }
+    }
+}
+
+// walk the RIFF chunk list and return the format and sample data of a WAV file
+pub fn read_wav(bytes: &[u8]) -> Result<Wav<'_>, WavError> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(WavError::NotRiff);
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut cursor = 12;
+    while cursor + 8 <= bytes.len() {
+        let id = &bytes[cursor..cursor + 4];
+        let size = LittleEndian::read_u32(&bytes[cursor + 4..cursor + 8]) as usize;
+        let start = cursor + 8;
+        // recorders that stream their output leave the size unset, so clamp it to what we received
+        let end = start.saturating_add(size).min(bytes.len());
+        match id {
+            b"fmt " => format = Some(read_format(&bytes[start..end])?),
+            b"data" => data = Some(&bytes[start..end]),
+            // LIST, fact, cue, ... carry nothing the analysis needs
+            _ => {}
+        }
+        // chunks are word aligned
+        cursor = start.saturating_add(size).saturating_add(size & 1);
+    }
+
+    let format = format.ok_or(WavError::MissingFormat)?;
+    let data = data.ok_or(WavError::MissingData)?;
+    check_supported(&format)?;
+    Ok(Wav { format, data })
+}
+
+fn read_format(fmt: &[u8]) -> Result<WavFormat, WavError> {
+    if fmt.len() < 16 {
+        return Err(WavError::Truncated);
+    }
+    let mut format_tag = LittleEndian::read_u16(&fmt[0..2]);
+    if format_tag == WAVE_FORMAT_EXTENSIBLE {
+        // cbSize, valid bits and channel mask come before the sub format GUID
+        if fmt.len() < 40 {
+            return Err(WavError::Truncated);
+        }
+        let guid = &fmt[24..40];
+        if guid[2..] != SUBFORMAT_GUID_SUFFIX {
+            return Err(WavError::Unsupported(String::from("unknown extensible sub format")));
+        }
+        format_tag = LittleEndian::read_u16(&guid[0..2]);
+    }
+    Ok(WavFormat {
+        format_tag,
+        channels: LittleEndian::read_u16(&fmt[2..4]),
+        sample_rate: LittleEndian::read_u32(&fmt[4..8]),
+        // samples with less valid bits are left-justified in their container, so decode by container size
+        bits_per_sample: LittleEndian::read_u16(&fmt[14..16]),
+    })
+}
+
+fn check_supported(format: &WavFormat) -> Result<(), WavError> {
+    format.sample_format()?;
+    if format.channels == 0 {
+        return Err(WavError::Unsupported(String::from("no channels")));
+    }
+    if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&format.sample_rate) {
+        return Err(WavError::Unsupported(format!("sample rate of {} Hz, expected {} to {} Hz", format.sample_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE)));
+    }
+    Ok(())
+}
 
 pub enum Oscilator {
     SINE,
@@ -56,21 +248,21 @@ pub fn generate_wav(note: &PhiNote, oscilator: Oscilator) -> Vec<u8> {
     match oscilator {
         Oscilator::SINE => {
             // generate a sine wave
-            for i in 0..number_of_samples {
+            for (i, sample) in wave.iter_mut().enumerate() {
                 let angle = 2.0 * PI  * (i as f64 / samples_per_cycle);
                 let amplitude = (angle.sin() * max_amplitude as f64) as i16;
-                wave[i] = amplitude;
+                *sample = amplitude;
             }
         },
         Oscilator::SQUARE => {
             // generate a square wave
-            let step = ((max_amplitude as f64 - min_amplitude as f64) / samples_per_cycle as f64) as i16;
+            let step = ((max_amplitude as f64 - min_amplitude as f64) / samples_per_cycle) as i16;
             let mut amplitude = min_amplitude;
-            for i in 0..number_of_samples {
+            for (i, sample) in wave.iter_mut().enumerate() {
                 if i as f64 % (samples_per_cycle).round() == 0.0 {
                     amplitude = min_amplitude;
                 }
-                wave[i] = amplitude;
+                *sample = amplitude;
                 if step as i32 + amplitude as i32 <= i16::MAX as i32 {
                     amplitude += step;
                 }
@@ -78,27 +270,27 @@ pub fn generate_wav(note: &PhiNote, oscilator: Oscilator) -> Vec<u8> {
         },
         Oscilator::SAWTOOTH => {
             // generate a sawtooth wave
-            let step = ((max_amplitude as f64 - min_amplitude as f64) / samples_per_cycle as f64) as i16;
+            let step = ((max_amplitude as f64 - min_amplitude as f64) / samples_per_cycle) as i16;
             let mut amplitude = min_amplitude;
-            for i in 0..number_of_samples {
+            for (i, sample) in wave.iter_mut().enumerate() {
                 if i as f64 % (samples_per_cycle).round() == 0.0 {
                     amplitude = min_amplitude;
                 }
-                wave[i] = amplitude;
+                *sample = amplitude;
                 amplitude += step;
             }
         },
         Oscilator::TRIANGLE => {
             // generate a triangle wave
-            let step = ((max_amplitude as f64 - min_amplitude as f64) / samples_per_cycle as f64) as i16;
+            let step = ((max_amplitude as f64 - min_amplitude as f64) / samples_per_cycle) as i16;
             let mut amplitude = min_amplitude;
             let mut up = true;
-            for i in 0..number_of_samples {
+            for (i, sample) in wave.iter_mut().enumerate() {
                 if i as f64 % (samples_per_cycle).round() == 0.0 {
                     amplitude = min_amplitude;
                     up = true;
                 }
-                wave[i] = amplitude;
+                *sample = amplitude;
                 if up {
                     amplitude += step;
                     if amplitude >= max_amplitude {
@@ -114,3 +306,130 @@ pub fn generate_wav(note: &PhiNote, oscilator: Oscilator) -> Vec<u8> {
     wave.iter().flat_map(|&x| x.to_le_bytes().to_vec()).collect()
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_wav() {
+        let pcm = vec![1u8, 0, 2, 0, 3, 0];
+        let file = to_wav_file(&pcm, 16, 1, 48000);
+
+        let wav = read_wav(&file).unwrap();
+        assert_eq!(WavFormat::pcm(1, 48000, 16), wav.format);
+        assert_eq!(&pcm[..], wav.data);
+    }
+
+    #[test]
+    fn test_read_wav_skips_unknown_chunks() {
+        let pcm = vec![1u8, 0, 2, 0];
+        let file = to_wav_file(&pcm, 16, 1, 44100);
+
+        // insert an odd sized LIST chunk (plus its pad byte) between fmt and data
+        let mut patched = file[..36].to_vec();
+        patched.extend_from_slice(b"LIST");
+        patched.write_u32::<LittleEndian>(3).unwrap();
+        patched.extend_from_slice(&[b'a', b'b', b'c', 0]);
+        patched.extend_from_slice(&file[36..]);
+
+        let wav = read_wav(&patched).unwrap();
+        assert_eq!(&pcm[..], wav.data);
+    }
+
+    #[test]
+    fn test_read_wav_clamps_data_size() {
+        let pcm = vec![1u8, 0, 2, 0];
+        let mut file = to_wav_file(&pcm, 16, 1, 44100);
+        // streamed files often carry a placeholder size
+        file[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let wav = read_wav(&file).unwrap();
+        assert_eq!(&pcm[..], wav.data);
+    }
+
+    #[test]
+    fn test_read_wav_extensible() {
+        let pcm = 0.5f32.to_le_bytes().to_vec();
+        let file = to_wav_file(&pcm, 32, 1, 44100);
+
+        // rebuild the fmt chunk as a 40 bytes WAVE_FORMAT_EXTENSIBLE one
+        let mut patched = file[..12].to_vec();
+        patched.extend_from_slice(b"fmt ");
+        patched.write_u32::<LittleEndian>(40).unwrap();
+        patched.write_u16::<LittleEndian>(WAVE_FORMAT_EXTENSIBLE).unwrap();
+        patched.extend_from_slice(&file[22..36]);
+        patched.write_u16::<LittleEndian>(22).unwrap();
+        patched.write_u16::<LittleEndian>(32).unwrap();
+        patched.write_u32::<LittleEndian>(0x4).unwrap();
+        patched.write_u16::<LittleEndian>(WAVE_FORMAT_IEEE_FLOAT).unwrap();
+        patched.extend_from_slice(&SUBFORMAT_GUID_SUFFIX);
+        patched.extend_from_slice(&file[36..]);
+
+        let wav = read_wav(&patched).unwrap();
+        assert_eq!(WAVE_FORMAT_IEEE_FLOAT, wav.format.format_tag);
+        assert_eq!(Ok(SampleFormat::F32), wav.format.sample_format());
+        assert_eq!(Ok(vec![0.5]), wav.samples());
+    }
+
+    #[test]
+    fn test_decode_samples() {
+        let dataset: [(SampleFormat, Vec<u8>, f64); 10] = [
+            (SampleFormat::U8, vec![0x80], 0.0),
+            (SampleFormat::U8, vec![0x00], -1.0),
+            (SampleFormat::I16, vec![0x00, 0x40], 0.5),
+            (SampleFormat::I16, vec![0x00, 0x80], -1.0),
+            (SampleFormat::I24, vec![0x00, 0x00, 0x40], 0.5),
+            (SampleFormat::I24, vec![0x00, 0x00, 0xC0], -0.5),
+            (SampleFormat::I32, vec![0x00, 0x00, 0x00, 0x40], 0.5),
+            (SampleFormat::I32, vec![0x00, 0x00, 0x00, 0x80], -1.0),
+            (SampleFormat::F32, (-0.25f32).to_le_bytes().to_vec(), -0.25),
+            (SampleFormat::F64, 0.75f64.to_le_bytes().to_vec(), 0.75),
+        ];
+
+        for data in dataset {
+            assert_eq!(data.0.bytes(), data.1.len());
+            assert_eq!(data.2, data.0.decode(&data.1));
+        }
+
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(0.0, SampleFormat::F32.decode(&value.to_le_bytes()));
+        }
+        assert_eq!(0.0, SampleFormat::F64.decode(&f64::NAN.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_samples_drops_incomplete_sample() {
+        let data = [0x00, 0x00, 0x40, 0x00, 0x00, 0xC0, 0x12];
+        let wav = Wav {
+            format: WavFormat::pcm(1, 44100, 24),
+            data: &data
+        };
+        assert_eq!(Ok(vec![0.5, -0.5]), wav.samples());
+    }
+
+    #[test]
+    fn test_read_wav_errors() {
+        assert_eq!(Some(WavError::NotRiff), read_wav(&[0u8; 4]).err());
+        assert_eq!(Some(WavError::NotRiff), read_wav(&[1u8, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0]).err());
+
+        let file = to_wav_file(&[], 16, 1, 44100);
+        assert_eq!(Some(WavError::MissingData), read_wav(&file[..36]).err());
+
+        let file = to_wav_file(&[0, 0], 12, 1, 44100);
+        assert!(matches!(read_wav(&file), Err(WavError::Unsupported(_))));
+
+        let mut file = to_wav_file(&[0, 0], 16, 1, 44100);
+        // A-law
+        file[20] = 0x06;
+        assert!(matches!(read_wav(&file), Err(WavError::Unsupported(_))));
+
+        for sample_rate in [0, 1, 7999, 192001, 1_000_000] {
+            let file = to_wav_file(&[0, 0], 16, 1, sample_rate);
+            assert!(matches!(read_wav(&file), Err(WavError::Unsupported(_))), "{}", sample_rate);
+        }
+        assert!(read_wav(&to_wav_file(&[0, 0], 16, 1, MIN_SAMPLE_RATE)).is_ok());
+        assert!(read_wav(&to_wav_file(&[0, 0], 16, 1, MAX_SAMPLE_RATE)).is_ok());
+    }
+}
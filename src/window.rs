@@ -0,0 +1,151 @@
+use std::{f64::consts::PI, fmt::Display, str::FromStr};
+
+// shape parameter used when a Kaiser window is requested without one
+pub const DEFAULT_KAISER_BETA: f64 = 8.6;
+// largest shape parameter accepted, far narrower than any useful window yet well below where
+// the Bessel function overflows
+pub const MAX_KAISER_BETA: f64 = 50.0;
+
+// tapering applied to a frame before it is transformed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WindowFunction {
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    Kaiser(f64),
+}
+
+// zeroth order modified Bessel function of the first kind
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= half / k as f64;
+        sum += term * term;
+        if term * term < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+impl WindowFunction {
+    // periodic window of n coefficients, the form suited to spectral analysis
+    pub fn coefficients(&self, n: usize) -> Vec<f64> {
+        (0..n).map(|i| {
+            let x = i as f64 / n as f64;
+            match self {
+                WindowFunction::Rectangular => 1.0,
+                WindowFunction::Hann => 0.5 - 0.5 * (2.0 * PI * x).cos(),
+                WindowFunction::Hamming => 0.54 - 0.46 * (2.0 * PI * x).cos(),
+                WindowFunction::BlackmanHarris => {
+                    0.35875 - 0.48829 * (2.0 * PI * x).cos() + 0.14128 * (4.0 * PI * x).cos()
+                        - 0.01168 * (6.0 * PI * x).cos()
+                },
+                WindowFunction::Kaiser(beta) => {
+                    let r = 2.0 * x - 1.0;
+                    bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(*beta)
+                },
+            }
+        }).collect()
+    }
+
+    pub fn apply(&self, frame: &[f64]) -> Vec<f64> {
+        if *self == WindowFunction::Rectangular {
+            return frame.to_vec();
+        }
+        frame.iter()
+            .zip(self.coefficients(frame.len()))
+            .map(|(x, w)| x * w)
+            .collect()
+    }
+}
+
+impl FromStr for WindowFunction {
+    type Err = String;
+
+    // kaiser takes an optional shape parameter, as in kaiser:12
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rectangular" => Ok(WindowFunction::Rectangular),
+            "hann" => Ok(WindowFunction::Hann),
+            "hamming" => Ok(WindowFunction::Hamming),
+            "blackman-harris" => Ok(WindowFunction::BlackmanHarris),
+            "kaiser" => Ok(WindowFunction::Kaiser(DEFAULT_KAISER_BETA)),
+            _ => match s.strip_prefix("kaiser:").map(|beta| beta.parse::<f64>()) {
+                Some(Ok(beta)) if (0.0..=MAX_KAISER_BETA).contains(&beta) => Ok(WindowFunction::Kaiser(beta)),
+                _ => Err(format!("unknown window function '{}'", s)),
+            },
+        }
+    }
+}
+
+impl Display for WindowFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WindowFunction::Rectangular => write!(f, "rectangular"),
+            WindowFunction::Hann => write!(f, "hann"),
+            WindowFunction::Hamming => write!(f, "hamming"),
+            WindowFunction::BlackmanHarris => write!(f, "blackman-harris"),
+            WindowFunction::Kaiser(beta) => write!(f, "kaiser:{}", beta),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bessel_i0() {
+        assert_eq!(1.0, bessel_i0(0.0));
+        assert!((bessel_i0(1.0) - 1.2660658777520082).abs() < 1e-12);
+        assert!((bessel_i0(8.6) - 750.46116).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_coefficients() {
+        let windows = [
+            (WindowFunction::Rectangular, 1.0, 1.0),
+            (WindowFunction::Hann, 0.0, 1.0),
+            (WindowFunction::Hamming, 0.08, 1.0),
+            (WindowFunction::BlackmanHarris, 0.00006, 1.0),
+            (WindowFunction::Kaiser(8.6), 1.0 / bessel_i0(8.6), 1.0),
+        ];
+        for (window, edge, center) in windows {
+            let coefficients = window.coefficients(64);
+            assert_eq!(64, coefficients.len());
+            assert!((coefficients[0] - edge).abs() < 1e-9, "{} edge {}", window, coefficients[0]);
+            assert!((coefficients[32] - center).abs() < 1e-9, "{} center {}", window, coefficients[32]);
+            // periodic windows are symmetric around their center sample
+            for i in 1..32 {
+                assert!((coefficients[32 - i] - coefficients[32 + i]).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let frame = [1.0, 1.0, 1.0, 1.0];
+        assert_eq!(frame.to_vec(), WindowFunction::Rectangular.apply(&frame));
+        let windowed = WindowFunction::Hann.apply(&frame);
+        assert!((windowed[0]).abs() < 1e-12);
+        assert!((windowed[1] - 0.5).abs() < 1e-12);
+        assert!((windowed[2] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_parse_window_function() {
+        for window in [WindowFunction::Rectangular, WindowFunction::Hann, WindowFunction::Hamming, WindowFunction::BlackmanHarris, WindowFunction::Kaiser(5.5)] {
+            assert_eq!(Ok(window), window.to_string().parse());
+        }
+        assert_eq!(Ok(WindowFunction::Kaiser(DEFAULT_KAISER_BETA)), "kaiser".parse());
+        for window in ["kaiser:-1", "kaiser:inf", "kaiser:NaN", "kaiser:1000"] {
+            assert!(window.parse::<WindowFunction>().is_err(), "{}", window);
+        }
+        assert!("triangle".parse::<WindowFunction>().is_err());
+    }
+}